@@ -0,0 +1,276 @@
+use crate::config::{Config, ZoneDiscovery};
+use crate::opnsense::{Backend, DnsBackend, Opnsense};
+use crate::{managed_zones, rewrite, tls, unserved_zones};
+use reqwest::StatusCode;
+use std::fmt::Display;
+use std::net::ToSocketAddrs;
+
+// run validates the configuration and performs a read-only
+// self-test against OPNsense, printing a report to stdout.
+// It fails if any check failed.
+pub async fn run(config: &Config) -> anyhow::Result<()> {
+    let mut report = Report::default();
+
+    validate(config, &mut report);
+
+    match Opnsense::try_from(config) {
+        Ok(opnsense) => self_test(config, &opnsense, &mut report).await,
+        Err(e) => report.fail(format_args!("could not build opnsense client: {e:#}")),
+    }
+
+    for replica in &config.replicas {
+        println!();
+        println!("replica {}", replica.name);
+
+        let config = config.for_replica(replica);
+        match Opnsense::try_from(&config) {
+            Ok(opnsense) => self_test(&config, &opnsense, &mut report).await,
+            Err(e) => report.fail(format_args!("could not build opnsense client: {e:#}")),
+        }
+    }
+
+    if !report.missing_privileges.is_empty() {
+        println!();
+        println!("the API user is missing privileges for:");
+        for endpoint in &report.missing_privileges {
+            println!("  - {endpoint}");
+        }
+    }
+
+    match report.failures {
+        0 => Ok(()),
+        n => Err(anyhow::anyhow!("{n} check(s) failed")),
+    }
+}
+
+fn validate(config: &Config, report: &mut Report) {
+    match config.bind.to_socket_addrs() {
+        Ok(_) => report.ok(format_args!("bind address {}", config.bind)),
+        Err(e) => report.fail(format_args!("bind address {}: {e}", config.bind)),
+    }
+
+    if config.domain_filters.is_empty() && config.zone_discovery != ZoneDiscovery::Static {
+        report.warn("no domain_filters set, every transparent local zone will be managed");
+    }
+    for filter in &config.domain_filters {
+        match validate_filter(filter) {
+            Ok(()) => report.ok(format_args!("domain filter {filter:?}")),
+            Err(e) => report.fail(format_args!("domain filter {filter:?}: {e}")),
+        }
+    }
+
+    if config.zone_discovery != ZoneDiscovery::Discover {
+        if config.static_zones.is_empty() {
+            report.fail("zone_discovery needs static_zones, none are set");
+        }
+        for zone in &config.static_zones {
+            match validate_filter(zone.trim_end_matches('.')) {
+                Ok(()) => report.ok(format_args!("static zone {zone:?}")),
+                Err(e) => report.fail(format_args!("static zone {zone:?}: {e}")),
+            }
+        }
+    }
+
+    match config.read_certificate_bundle() {
+        Ok(certs) if certs.is_empty() => report.ok("no certificate bundle, using system roots"),
+        Ok(certs) => report.ok(format_args!(
+            "certificate bundle with {} certificate(s)",
+            certs.len()
+        )),
+        Err(e) => report.fail(format_args!("certificate bundle: {e:#}")),
+    }
+    if config.gc.enabled {
+        if config.tenants.is_empty() && config.owner_id.is_none() {
+            report.fail("gc.enabled requires owner_id to be set");
+        }
+        for (name, tenant) in &config.tenants {
+            if config.for_tenant(name, tenant).owner_id.is_none() {
+                report.fail(format_args!(
+                    "tenant {name}: gc.enabled requires owner_id to be set"
+                ));
+            }
+        }
+    }
+    if config.allow_invalid_certs {
+        report.warn("allow_invalid_certs is set, the OPNsense certificate is not verified");
+    }
+
+    if !config.rewrites.is_empty() {
+        match rewrite::validate(&config.rewrites) {
+            Ok(()) => report.ok(format_args!("{} target rewrite(s)", config.rewrites.len())),
+            Err(e) => report.fail(format_args!("target rewrites: {e:#}")),
+        }
+    }
+
+    if let Some(tls) = &config.tls {
+        match tls::server_config(tls) {
+            Ok(_) => report.ok("listener tls configuration"),
+            Err(e) => report.fail(format_args!("listener tls configuration: {e:#}")),
+        }
+    }
+}
+
+fn validate_filter(filter: &str) -> anyhow::Result<()> {
+    let domain = filter.strip_prefix('.').unwrap_or(filter);
+
+    if domain.is_empty() {
+        anyhow::bail!("empty domain");
+    }
+    if domain.ends_with('.') {
+        anyhow::bail!("trailing dot");
+    }
+
+    for label in domain.split('.') {
+        if label.is_empty() || label.len() > 63 {
+            anyhow::bail!("invalid label length in {domain:?}");
+        }
+        if label.starts_with('-') || label.ends_with('-') {
+            anyhow::bail!("label {label:?} starts or ends with a hyphen");
+        }
+        if let Some(c) = label
+            .chars()
+            .find(|c| !(c.is_ascii_alphanumeric() || *c == '-' || *c == '_'))
+        {
+            anyhow::bail!("invalid character {c:?} in label {label:?}");
+        }
+    }
+
+    Ok(())
+}
+
+// Endpoints used by the self-test, as listed when the API user
+// lacks privileges for them.
+struct Endpoints {
+    zones: &'static str,
+    records: &'static str,
+    service: &'static str,
+}
+
+impl From<&DnsBackend> for Endpoints {
+    fn from(backend: &DnsBackend) -> Self {
+        match backend {
+            DnsBackend::Unbound(_) => Self {
+                zones: "api/unbound/diagnostics/listlocalzones",
+                records: "api/unbound/settings/searchHostOverride",
+                service: "api/unbound/service",
+            },
+            DnsBackend::Dnsmasq(_) => Self {
+                zones: "domain_filters",
+                records: "api/dnsmasq/settings/searchHost",
+                service: "api/dnsmasq/service",
+            },
+        }
+    }
+}
+
+async fn self_test(config: &Config, opnsense: &Opnsense, report: &mut Report) {
+    let backend = opnsense.backend();
+    let endpoints = Endpoints::from(&backend);
+
+    let records = match backend.list_records().await {
+        Ok(records) => {
+            report.ok(format_args!("authenticated against {}", config.base));
+            Some(records)
+        }
+        Err(e) if status(&e) == Some(StatusCode::UNAUTHORIZED) => {
+            report.fail(format_args!(
+                "authentication against {} failed, check key and secret",
+                config.base
+            ));
+            return;
+        }
+        Err(e) => {
+            report.api_error(endpoints.records, &e);
+            None
+        }
+    };
+
+    let zones = match backend.list_zones().await {
+        Ok(zones) => {
+            report.ok(format_args!("listed {} local zone(s)", zones.len()));
+            Some(zones)
+        }
+        // Static zones are meant for API users not allowed to
+        // list local zones, only their verification is skipped.
+        Err(e) if config.zone_discovery == ZoneDiscovery::Static => {
+            report.warn(format_args!(
+                "could not list local zones, static zones are not verified: {e:#}"
+            ));
+            None
+        }
+        Err(e) => {
+            report.api_error(endpoints.zones, &e);
+            None
+        }
+    };
+
+    if let Some(zones) = &zones {
+        for z in zones {
+            println!("        {} ({})", z.zone, z.r#type);
+        }
+        if config.zone_discovery != ZoneDiscovery::Discover {
+            for zone in unserved_zones(config, zones) {
+                report.warn(format_args!(
+                    "static zone {zone} is not served by {}",
+                    backend.name()
+                ));
+            }
+        }
+    }
+
+    let managed = managed_zones(config, zones.as_deref());
+    if zones.is_some() || config.zone_discovery == ZoneDiscovery::Static {
+        if managed.is_empty() {
+            report.fail("no zone survives the domain filters");
+        } else {
+            report.ok(format_args!("managed zones: {}", managed.join(", ")));
+        }
+    }
+
+    if let Some(records) = records {
+        let in_zones = records
+            .iter()
+            .filter(|r| managed.contains(&r.domain))
+            .count();
+        report.ok(format_args!(
+            "found {} host override(s), {in_zones} in managed zones",
+            records.len()
+        ));
+    }
+
+    match backend.status().await {
+        Ok(status) => report.ok(format_args!("{} service is {status}", backend.name())),
+        Err(e) => report.api_error(endpoints.service, &e),
+    }
+}
+
+fn status(e: &anyhow::Error) -> Option<StatusCode> {
+    e.downcast_ref::<reqwest::Error>().and_then(|e| e.status())
+}
+
+#[derive(Default)]
+struct Report {
+    failures: usize,
+    missing_privileges: Vec<&'static str>,
+}
+
+impl Report {
+    fn ok(&mut self, msg: impl Display) {
+        println!("ok      {msg}");
+    }
+    fn warn(&mut self, msg: impl Display) {
+        println!("warn    {msg}");
+    }
+    fn fail(&mut self, msg: impl Display) {
+        self.failures += 1;
+        println!("FAIL    {msg}");
+    }
+    fn api_error(&mut self, endpoint: &'static str, e: &anyhow::Error) {
+        if status(e) == Some(StatusCode::FORBIDDEN) {
+            self.missing_privileges.push(endpoint);
+            self.fail(format_args!("{endpoint}: permission denied"));
+        } else {
+            self.fail(format_args!("{endpoint}: {e:#}"));
+        }
+    }
+}
@@ -0,0 +1,254 @@
+use crate::config::Config;
+use crate::external_dns::{Endpoint, Targets};
+use crate::opnsense::unbound::HostOverrideRecord;
+use crate::opnsense::Backend;
+use crate::state::{AppState, DefaultRecordCache, DefaultZoneCache};
+use crate::{replicate, zones};
+use serde::Serialize;
+use std::net::IpAddr;
+
+// The cli module implements the one-off commands operating on
+// the host overrides the webhook manages, sharing the zone
+// discovery and filtering of the server.
+
+#[derive(clap::ValueEnum, Clone, Copy, Default)]
+pub enum OutputFormat {
+    #[default]
+    Table,
+    Json,
+}
+
+pub(crate) type CliState = AppState<DefaultRecordCache, DefaultZoneCache>;
+
+pub async fn list(
+    config: &Config,
+    zone: Option<String>,
+    output: OutputFormat,
+) -> anyhow::Result<()> {
+    let state = CliState::try_from(config)?;
+    let zone = zone.map(|z| z.trim_end_matches('.').to_owned());
+
+    let records = managed_records(&state)
+        .await?
+        .into_iter()
+        .filter(|r| zone.is_none() || zone.as_ref() == Some(&r.domain))
+        .collect::<Vec<_>>();
+
+    print_records(&records, output)
+}
+
+pub async fn get(
+    config: &Config,
+    fqdn: &str,
+    record_type: Option<String>,
+    output: OutputFormat,
+) -> anyhow::Result<()> {
+    let state = CliState::try_from(config)?;
+
+    let records = find_records(&state, fqdn, record_type.as_deref()).await?;
+    if records.is_empty() {
+        anyhow::bail!("no managed host override found for {fqdn}");
+    }
+
+    print_records(&records, output)
+}
+
+// set creates the host override for fqdn, or updates it
+// if one of the same type already exists.
+pub async fn set(
+    config: &Config,
+    fqdn: &str,
+    target: &str,
+    record_type: Option<String>,
+) -> anyhow::Result<()> {
+    let state = CliState::try_from(config)?;
+    let zones = zones(&state).await?;
+
+    let record_type = match record_type {
+        Some(t) => t.to_uppercase(),
+        None => match target.parse::<IpAddr>()? {
+            IpAddr::V4(_) => "A".to_owned(),
+            IpAddr::V6(_) => "AAAA".to_owned(),
+        },
+    };
+
+    let endpoint = Endpoint {
+        dns_name: fqdn.trim_end_matches('.').to_owned(),
+        targets: Targets(vec![target.to_owned()]),
+        record_type: record_type.clone(),
+        ..Default::default()
+    };
+    let record = endpoint
+        .get_record_for_zones(&zones)
+        .ok_or(anyhow::anyhow!("{fqdn} is not in a managed zone"))?;
+
+    let backend = state.opnsense.backend();
+    match find_records(&state, fqdn, Some(&record_type))
+        .await?
+        .first()
+    {
+        Some(existing) => {
+            backend.set_record(&existing.uuid, &record).await?;
+            println!("updated {fqdn} {record_type} {target}");
+            replicate(&state, &[], &[record], &[]).await;
+        }
+        None => {
+            let uuid = backend.add_record(&record).await?;
+            println!("created {fqdn} {record_type} {target} ({uuid})");
+            replicate(&state, &[], &[], &[record]).await;
+        }
+    }
+
+    backend.apply().await?;
+
+    Ok(())
+}
+
+pub async fn delete(
+    config: &Config,
+    fqdn: &str,
+    record_type: Option<String>,
+) -> anyhow::Result<()> {
+    let state = CliState::try_from(config)?;
+
+    let records = find_records(&state, fqdn, record_type.as_deref()).await?;
+    if records.is_empty() {
+        anyhow::bail!("no managed host override found for {fqdn}");
+    }
+
+    let backend = state.opnsense.backend();
+    let mut deleted = vec![];
+    let mut result = Ok(());
+    for r in records {
+        if let Err(e) = backend.delete_record(&r.uuid).await {
+            result = Err(e);
+            break;
+        }
+        println!("deleted {fqdn} {} {}", r.rr, r.server);
+        deleted.push(r);
+    }
+
+    replicate(&state, &deleted, &[], &[]).await;
+    result?;
+    backend.apply().await?;
+
+    Ok(())
+}
+
+pub async fn list_zones(config: &Config, output: OutputFormat) -> anyhow::Result<()> {
+    let state = CliState::try_from(config)?;
+
+    let mut zones = zones(&state).await?;
+    zones.sort();
+
+    match output {
+        OutputFormat::Table => zones.iter().for_each(|z| println!("{z}")),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&zones)?),
+    }
+
+    Ok(())
+}
+
+pub(crate) async fn managed_records(state: &CliState) -> anyhow::Result<Vec<HostOverrideRecord>> {
+    let zones = zones(state).await?;
+
+    Ok(state
+        .opnsense
+        .backend()
+        .list_records()
+        .await?
+        .into_iter()
+        .filter(|r| zones.contains(&r.domain))
+        .collect())
+}
+
+async fn find_records(
+    state: &CliState,
+    fqdn: &str,
+    record_type: Option<&str>,
+) -> anyhow::Result<Vec<HostOverrideRecord>> {
+    let fqdn = fqdn.trim_end_matches('.');
+
+    Ok(managed_records(state)
+        .await?
+        .into_iter()
+        .filter(|r| format!("{}.{}", r.hostname, r.domain) == fqdn)
+        .filter(|r| {
+            record_type
+                .map(|t| rr_type(r).eq_ignore_ascii_case(t))
+                .unwrap_or(true)
+        })
+        .collect())
+}
+
+pub(crate) fn rr_type(record: &HostOverrideRecord) -> &str {
+    record.rr.split_whitespace().next().unwrap_or_default()
+}
+
+#[derive(Serialize)]
+struct Row<'a> {
+    fqdn: String,
+    r#type: &'a str,
+    target: &'a str,
+    enabled: bool,
+    uuid: &'a str,
+    description: &'a str,
+}
+
+impl<'a> From<&'a HostOverrideRecord> for Row<'a> {
+    fn from(r: &'a HostOverrideRecord) -> Self {
+        Row {
+            fqdn: format!("{}.{}", r.hostname, r.domain),
+            r#type: rr_type(r),
+            target: if r.server.is_empty() {
+                &r.mx
+            } else {
+                &r.server
+            },
+            enabled: r.enabled.trim() == "1",
+            uuid: &r.uuid,
+            description: &r.description,
+        }
+    }
+}
+
+fn print_records(records: &[HostOverrideRecord], output: OutputFormat) -> anyhow::Result<()> {
+    let mut rows = records.iter().map(Row::from).collect::<Vec<_>>();
+    rows.sort_by(|a, b| (&a.fqdn, a.r#type).cmp(&(&b.fqdn, b.r#type)));
+
+    if let OutputFormat::Json = output {
+        println!("{}", serde_json::to_string_pretty(&rows)?);
+        return Ok(());
+    }
+
+    let table = std::iter::once(
+        ["FQDN", "TYPE", "TARGET", "ENABLED", "UUID", "DESCRIPTION"].map(String::from),
+    )
+    .chain(rows.iter().map(|r| {
+        [
+            r.fqdn.clone(),
+            r.r#type.to_owned(),
+            r.target.to_owned(),
+            r.enabled.to_string(),
+            r.uuid.to_owned(),
+            r.description.to_owned(),
+        ]
+    }))
+    .collect::<Vec<_>>();
+
+    let widths = (0..6)
+        .map(|i| table.iter().map(|r| r[i].len()).max().unwrap_or_default())
+        .collect::<Vec<_>>();
+
+    for row in &table {
+        let line = row
+            .iter()
+            .zip(&widths)
+            .map(|(c, w)| format!("{c:<w$}"))
+            .collect::<Vec<_>>()
+            .join("  ");
+        println!("{}", line.trim_end());
+    }
+
+    Ok(())
+}
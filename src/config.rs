@@ -1,13 +1,25 @@
+use anyhow::Context;
 use figment::{
-    providers::{Env, Format, Yaml},
-    Figment,
+    providers::{Env, Format, Json, Toml, Yaml},
+    Figment, Profile, Provider,
 };
+use ipnet::IpNet;
+use regex::Regex;
 use serde::{de, Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::path::PathBuf;
 
 #[derive(Clone, Deserialize, Debug)]
 pub struct Config {
+    #[serde(default)]
     pub key: String,
+    #[serde(default)]
+    pub key_file: Option<PathBuf>,
+    #[serde(default)]
     pub secret: String,
+    #[serde(default)]
+    pub secret_file: Option<PathBuf>,
     #[serde(deserialize_with = "from_str_deserialize")]
     pub base: reqwest::Url,
     #[serde(default = "default_bind")]
@@ -15,9 +27,286 @@ pub struct Config {
     #[serde(default)]
     pub domain_filters: Vec<String>,
     #[serde(default)]
+    pub zone_discovery: ZoneDiscovery,
+    #[serde(default)]
+    pub static_zones: Vec<String>,
+    #[serde(default)]
     pub allow_invalid_certs: bool,
     #[serde(deserialize_with = "deserialize_certificate", default)]
     pub certificate_bundle: Vec<reqwest::Certificate>,
+    #[serde(default)]
+    pub certificate_bundle_file: Option<PathBuf>,
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+    #[serde(default = "default_watch_interval")]
+    pub watch_interval: u64,
+    #[serde(default)]
+    pub owner_id: Option<String>,
+    #[serde(default)]
+    pub gc: GcConfig,
+    #[serde(default)]
+    pub drift: DriftConfig,
+    #[serde(default)]
+    pub record_cache: RecordCacheConfig,
+    #[serde(default = "default_concurrency")]
+    pub concurrency: usize,
+    #[serde(default)]
+    pub rate_limit: Option<RateLimitConfig>,
+    #[serde(default)]
+    pub listing_cache_ttl: u64,
+    #[serde(default)]
+    pub backend: BackendKind,
+    #[serde(default)]
+    pub replicas: Vec<ReplicaConfig>,
+    #[serde(default)]
+    pub tenants: BTreeMap<String, TenantConfig>,
+    #[serde(default)]
+    pub tenant_header: Option<String>,
+    // tenant names the tenant this configuration was derived for.
+    #[serde(skip)]
+    pub tenant: Option<String>,
+    #[serde(default)]
+    pub targets: TargetsConfig,
+    #[serde(default)]
+    pub rewrites: Vec<RewriteConfig>,
+    #[serde(default)]
+    pub name_rewrites: Vec<NameRewriteConfig>,
+    #[serde(default)]
+    pub zones: BTreeMap<String, ZoneConfig>,
+}
+
+// ZoneConfig overrides, for one managed zone, how its records
+// are handled. The description template may refer to {fqdn},
+// {hostname}, {zone} and {type}.
+#[derive(Clone, Deserialize, Debug, Default)]
+pub struct ZoneConfig {
+    #[serde(default)]
+    pub read_only: bool,
+    #[serde(default)]
+    pub record_types: Option<Vec<String>>,
+    #[serde(default)]
+    pub delete_mode: Option<DeleteMode>,
+    #[serde(default)]
+    pub targets: Option<TargetsConfig>,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+impl ZoneConfig {
+    pub fn allows_type(&self, record_type: &str) -> bool {
+        let record_type = record_type.split_whitespace().next().unwrap_or_default();
+
+        match &self.record_types {
+            None => true,
+            Some(types) => types.iter().any(|t| t.eq_ignore_ascii_case(record_type)),
+        }
+    }
+}
+
+// NameRewriteConfig maps names published by external-dns to the
+// names of host overrides. Regular expressions cannot be inverted,
+// so regex rules give the expression mapping names back as well.
+#[derive(Clone, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum NameRewriteConfig {
+    Suffix {
+        from: String,
+        to: String,
+    },
+    Regex {
+        #[serde(deserialize_with = "deserialize_regex")]
+        from: Regex,
+        to: String,
+        #[serde(deserialize_with = "deserialize_regex")]
+        reverse_from: Regex,
+        reverse_to: String,
+        #[serde(default)]
+        domain: Option<String>,
+    },
+}
+
+// RewriteConfig maps targets within from to the same offset
+// within to, optionally only in some zones. Single addresses can
+// be given without a prefix length.
+#[derive(Clone, Deserialize, Debug)]
+pub struct RewriteConfig {
+    #[serde(deserialize_with = "deserialize_net")]
+    pub from: IpNet,
+    #[serde(deserialize_with = "deserialize_net")]
+    pub to: IpNet,
+    #[serde(default, deserialize_with = "deserialize_zones")]
+    pub zones: Vec<String>,
+}
+
+// TargetsConfig restricts the addresses records may point to.
+#[derive(Clone, Deserialize, Debug, Default)]
+pub struct TargetsConfig {
+    #[serde(default)]
+    pub allow: Vec<IpNet>,
+    #[serde(default)]
+    pub deny: Vec<IpNet>,
+}
+
+// TenantConfig holds the settings of one of several external-dns
+// instances served by the webhook under /<name>. Settings left
+// unset are taken from the top level.
+#[derive(Clone, Deserialize, Debug)]
+pub struct TenantConfig {
+    #[serde(default)]
+    pub domain_filters: Option<Vec<String>>,
+    #[serde(default)]
+    pub owner_id: Option<String>,
+    #[serde(default)]
+    pub record_cache: Option<RecordCacheConfig>,
+}
+
+// ReplicaConfig describes an additional OPNsense instance the
+// changes applied to the primary one are replicated to. Connection
+// settings left unset are taken from the primary instance, and
+// zones restricts the replica to some of the managed zones.
+#[derive(Clone, Deserialize, Debug)]
+pub struct ReplicaConfig {
+    pub name: String,
+    #[serde(deserialize_with = "from_str_deserialize")]
+    pub base: reqwest::Url,
+    #[serde(default)]
+    pub key: Option<String>,
+    #[serde(default)]
+    pub key_file: Option<PathBuf>,
+    #[serde(default)]
+    pub secret: Option<String>,
+    #[serde(default)]
+    pub secret_file: Option<PathBuf>,
+    #[serde(default)]
+    pub allow_invalid_certs: Option<bool>,
+    #[serde(deserialize_with = "deserialize_certificate", default)]
+    pub certificate_bundle: Vec<reqwest::Certificate>,
+    #[serde(default)]
+    pub certificate_bundle_file: Option<PathBuf>,
+    #[serde(default)]
+    pub zones: Vec<String>,
+}
+
+// ZoneDiscovery selects where the managed zones come from: the
+// local zones listed by OPNsense, the static_zones list, or both.
+#[derive(Clone, Copy, Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ZoneDiscovery {
+    #[default]
+    Discover,
+    Static,
+    Union,
+}
+
+// BackendKind selects the OPNsense DNS service records are
+// managed in.
+#[derive(Clone, Copy, Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    #[default]
+    Unbound,
+    Dnsmasq,
+}
+
+// RecordCacheConfig selects where the mapping of records to
+// host override UUIDs is kept.
+#[derive(Clone, Deserialize, Debug, Default)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum RecordCacheConfig {
+    #[default]
+    Memory,
+    File {
+        path: PathBuf,
+    },
+}
+
+// GcConfig controls the collection of owned host overrides
+// external-dns no longer asks for. Durations are in seconds.
+#[derive(Clone, Deserialize, Debug)]
+pub struct GcConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_gc_interval")]
+    pub interval: u64,
+    #[serde(default = "default_gc_grace_period")]
+    pub grace_period: u64,
+    #[serde(default)]
+    pub mode: DeleteMode,
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+impl Default for GcConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval: default_gc_interval(),
+            grace_period: default_gc_grace_period(),
+            mode: DeleteMode::default(),
+            dry_run: false,
+        }
+    }
+}
+
+// DriftConfig controls the periodic comparison of the host
+// overrides written by the webhook with their state in OPNsense.
+#[derive(Clone, Deserialize, Debug)]
+pub struct DriftConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_drift_interval")]
+    pub interval: u64,
+    #[serde(default)]
+    pub repair: bool,
+}
+
+impl Default for DriftConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval: default_drift_interval(),
+            repair: false,
+        }
+    }
+}
+
+// RateLimitConfig throttles the calls made to the OPNsense API
+// to requests_per_second on average, allowing bursts of up to
+// burst calls.
+#[derive(Clone, Deserialize, Debug)]
+pub struct RateLimitConfig {
+    pub requests_per_second: f64,
+    #[serde(default = "default_rate_limit_burst")]
+    pub burst: u32,
+}
+
+#[derive(Clone, Copy, Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DeleteMode {
+    #[default]
+    Delete,
+    Disable,
+}
+
+// TlsConfig enables HTTPS on the webhook listener. Files are
+// re-read when they change on disk, and client certificates are
+// required when client_ca_file is set.
+#[derive(Clone, Deserialize, Debug)]
+pub struct TlsConfig {
+    pub cert_file: PathBuf,
+    pub key_file: PathBuf,
+    #[serde(default)]
+    pub client_ca_file: Option<PathBuf>,
+}
+
+impl TlsConfig {
+    pub fn files(&self) -> Vec<PathBuf> {
+        [&self.cert_file, &self.key_file]
+            .into_iter()
+            .chain(self.client_ca_file.as_ref())
+            .cloned()
+            .collect()
+    }
 }
 
 fn from_str_deserialize<'de, D, T>(deserializer: D) -> Result<T, D::Error>
@@ -34,6 +323,46 @@ fn default_bind() -> String {
     "127.0.0.1:8800".to_owned()
 }
 
+fn default_concurrency() -> usize {
+    1
+}
+
+fn default_rate_limit_burst() -> u32 {
+    1
+}
+
+fn default_gc_interval() -> u64 {
+    300
+}
+
+fn default_gc_grace_period() -> u64 {
+    3600
+}
+
+fn default_drift_interval() -> u64 {
+    300
+}
+
+fn read_inline_or_file(name: &str, inline: &str, file: Option<&PathBuf>) -> anyhow::Result<String> {
+    let value = match file {
+        Some(path) => std::fs::read_to_string(path)
+            .with_context(|| format!("could not read {name} from {}", path.display()))?
+            .trim()
+            .to_owned(),
+        None => inline.to_owned(),
+    };
+
+    if value.is_empty() {
+        anyhow::bail!("missing {name}: set either {name} or {name}_file");
+    }
+
+    Ok(value)
+}
+
+fn default_watch_interval() -> u64 {
+    30
+}
+
 fn deserialize_certificate<'de, D>(deserializer: D) -> Result<Vec<reqwest::Certificate>, D::Error>
 where
     D: Deserializer<'de>,
@@ -46,11 +375,418 @@ where
     })
 }
 
+fn deserialize_regex<'de, D>(deserializer: D) -> Result<Regex, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Regex::new(&String::deserialize(deserializer)?).map_err(de::Error::custom)
+}
+
+fn deserialize_net<'de, D>(deserializer: D) -> Result<IpNet, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+
+    match s.parse::<IpAddr>() {
+        Ok(ip) => Ok(ip.into()),
+        Err(_) => s.parse::<IpNet>().map_err(de::Error::custom),
+    }
+}
+
+// deserialize_zones strips the leading and trailing dots from
+// zone names, which are compared with record domains as is.
+fn deserialize_zones<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|z| z.trim_matches('.').to_owned())
+        .collect())
+}
+
 impl Config {
-    pub fn try_from_env() -> figment::Result<Config> {
-        Figment::new()
-            .merge(Yaml::file("config.yaml"))
-            .merge(Env::prefixed("OPNSENSE_"))
+    // read_key returns the API key, preferring key_file over
+    // the inline value so it can be rotated on disk.
+    pub fn read_key(&self) -> anyhow::Result<String> {
+        read_inline_or_file("key", &self.key, self.key_file.as_ref())
+    }
+
+    pub fn read_secret(&self) -> anyhow::Result<String> {
+        read_inline_or_file("secret", &self.secret, self.secret_file.as_ref())
+    }
+
+    // read_certificate_bundle returns the inline certificates
+    // followed by those found in certificate_bundle_file.
+    pub fn read_certificate_bundle(&self) -> anyhow::Result<Vec<reqwest::Certificate>> {
+        let mut certs = self.certificate_bundle.clone();
+
+        if let Some(path) = &self.certificate_bundle_file {
+            let pem = std::fs::read(path)
+                .with_context(|| format!("could not read {}", path.display()))?;
+            certs.extend(reqwest::Certificate::from_pem_bundle(&pem)?);
+        }
+
+        Ok(certs)
+    }
+
+    // secret_files lists the files backing credentials, which
+    // are watched so rotated secrets are picked up at runtime.
+    pub fn secret_files(&self) -> Vec<PathBuf> {
+        [
+            &self.key_file,
+            &self.secret_file,
+            &self.certificate_bundle_file,
+        ]
+        .into_iter()
+        .flatten()
+        .cloned()
+        .collect()
+    }
+
+    // zone returns the overrides configured for zone, if any.
+    pub fn zone(&self, zone: &str) -> Option<&ZoneConfig> {
+        self.zones.get(zone.trim_matches('.'))
+    }
+
+    // manages_type tells whether records of record_type are
+    // managed in zone.
+    pub fn manages_type(&self, zone: &str, record_type: &str) -> bool {
+        match self.zone(zone) {
+            None => true,
+            Some(z) => z.allows_type(record_type),
+        }
+    }
+
+    // targets_for returns the target restrictions of zone.
+    pub fn targets_for(&self, zone: &str) -> &TargetsConfig {
+        self.zone(zone)
+            .and_then(|z| z.targets.as_ref())
+            .unwrap_or(&self.targets)
+    }
+
+    // for_replica returns the configuration to connect to the
+    // replica with, i.e. this one with the replica's connection
+    // settings applied.
+    pub fn for_replica(&self, replica: &ReplicaConfig) -> Config {
+        let mut config = self.clone();
+        config.base = replica.base.clone();
+        config.replicas = Vec::new();
+
+        if replica.key.is_some() || replica.key_file.is_some() {
+            config.key = replica.key.clone().unwrap_or_default();
+            config.key_file = replica.key_file.clone();
+        }
+        if replica.secret.is_some() || replica.secret_file.is_some() {
+            config.secret = replica.secret.clone().unwrap_or_default();
+            config.secret_file = replica.secret_file.clone();
+        }
+        if let Some(allow) = replica.allow_invalid_certs {
+            config.allow_invalid_certs = allow;
+        }
+        if !replica.certificate_bundle.is_empty() || replica.certificate_bundle_file.is_some() {
+            config.certificate_bundle = replica.certificate_bundle.clone();
+            config.certificate_bundle_file = replica.certificate_bundle_file.clone();
+        }
+
+        config
+    }
+
+    // for_tenant returns the configuration of the tenant, i.e.
+    // this one with the tenant's settings applied.
+    pub fn for_tenant(&self, name: &str, tenant: &TenantConfig) -> Config {
+        let mut config = self.clone();
+        config.tenants = BTreeMap::new();
+        config.tenant = Some(name.to_owned());
+
+        if let Some(filters) = &tenant.domain_filters {
+            config.domain_filters = filters.clone();
+        }
+        if let Some(owner_id) = &tenant.owner_id {
+            config.owner_id = Some(owner_id.clone());
+        }
+        if let Some(record_cache) = &tenant.record_cache {
+            config.record_cache = record_cache.clone();
+        }
+
+        config
+    }
+
+    // load merges the given files in order, later files taking
+    // precedence, followed by OPNSENSE_* environment variables.
+    // Without any file, config.yaml is used if it exists.
+    pub fn load(files: &[PathBuf]) -> anyhow::Result<Config> {
+        let mut figment = Figment::new();
+
+        if files.is_empty() {
+            figment = figment.merge(Yaml::file("config.yaml"));
+        }
+
+        for path in files {
+            if !path.is_file() {
+                anyhow::bail!("config file not found: {}", path.display());
+            }
+
+            figment = match path.extension().and_then(|e| e.to_str()) {
+                Some("yaml" | "yml") => figment.merge(Yaml::file_exact(path)),
+                Some("toml") => figment.merge(Toml::file_exact(path)),
+                Some("json") => figment.merge(Json::file_exact(path)),
+                _ => anyhow::bail!("unsupported config file format: {}", path.display()),
+            };
+        }
+
+        let figment = figment.merge(Env::prefixed("OPNSENSE_").ignore(&["config"]));
+
+        log_sources(&figment)?;
+
+        let config: Config = figment.extract()?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    // validate rejects combinations of settings that would only
+    // fail once records are written.
+    fn validate(&self) -> anyhow::Result<()> {
+        // tokio intervals cannot tick every 0 seconds.
+        if self.watch_interval == 0 {
+            anyhow::bail!("watch_interval must be greater than 0");
+        }
+        if self.gc.enabled && self.gc.interval == 0 {
+            anyhow::bail!("gc.interval must be greater than 0");
+        }
+        if self.drift.enabled && self.drift.interval == 0 {
+            anyhow::bail!("drift.interval must be greater than 0");
+        }
+
+        if self.backend == BackendKind::Dnsmasq {
+            if self.gc.enabled && self.gc.mode == DeleteMode::Disable {
+                anyhow::bail!(
+                    "gc.mode disable is not supported with the dnsmasq backend, host entries cannot be disabled"
+                );
+            }
+            if let Some((zone, _)) = self
+                .zones
+                .iter()
+                .find(|(_, z)| z.delete_mode == Some(DeleteMode::Disable))
+            {
+                anyhow::bail!(
+                    "zone {zone}: delete_mode disable is not supported with the dnsmasq backend, host entries cannot be disabled"
+                );
+            }
+            // The dnsmasq zones are the domain filters, with none
+            // set nothing would be managed.
+            if self.zone_discovery != ZoneDiscovery::Static {
+                let unfiltered = match self.tenants.is_empty() {
+                    true => self.domain_filters.is_empty(),
+                    false => self
+                        .tenants
+                        .iter()
+                        .any(|(name, t)| self.for_tenant(name, t).domain_filters.is_empty()),
+                };
+                if unfiltered {
+                    anyhow::bail!(
+                        "domain_filters must be set with the dnsmasq backend, unless zone_discovery is static"
+                    );
+                }
+            }
+        }
+
+        self.validate_tenants()
+    }
+
+    // validate_tenants rejects tenants that could touch each other's
+    // records: overlapping domain filters, or with garbage collection
+    // enabled, a shared owner ID.
+    fn validate_tenants(&self) -> anyhow::Result<()> {
+        let tenants: Vec<_> = self
+            .tenants
+            .iter()
+            .map(|(name, tenant)| (name, self.for_tenant(name, tenant)))
+            .collect();
+
+        for (i, (name, config)) in tenants.iter().enumerate() {
+            for (other, other_config) in &tenants[i + 1..] {
+                if let Some((a, b)) =
+                    overlapping_filters(&config.domain_filters, &other_config.domain_filters)
+                {
+                    anyhow::bail!(
+                        "tenants {name} and {other} manage overlapping zones ({a} and {b})"
+                    );
+                }
+                if config.gc.enabled && config.owner_id == other_config.owner_id {
+                    anyhow::bail!(
+                        "tenants {name} and {other} share an owner_id with gc.enabled, garbage collection of one would remove the records of the other"
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// overlapping_filters returns a pair of domain filters, one of which
+// is the other or a subdomain of it. An empty list matches every
+// domain.
+fn overlapping_filters(a: &[String], b: &[String]) -> Option<(String, String)> {
+    let all = "*".to_string();
+    let a = if a.is_empty() {
+        vec![all.clone()]
+    } else {
+        a.to_vec()
+    };
+    let b = if b.is_empty() {
+        vec![all.clone()]
+    } else {
+        b.to_vec()
+    };
+
+    a.iter().find_map(|x| {
+        b.iter()
+            .find(|y| covers(x, y) || covers(y, x))
+            .map(|y| (x.clone(), y.clone()))
+    })
+}
+
+// covers reports whether the filter parent matches every domain
+// the filter child matches.
+fn covers(parent: &str, child: &str) -> bool {
+    let parent = parent.trim_matches('.').to_lowercase();
+    let child = child.trim_matches('.').to_lowercase();
+    parent == "*" || child == parent || child.ends_with(&format!(".{parent}"))
+}
+
+// Keys whose values are never logged.
+const SECRET_KEYS: &[&str] = &["key", "secret"];
+
+// log_sources logs, for every top level key, which provider
+// it was taken from, so layered configurations can be debugged.
+fn log_sources(figment: &Figment) -> anyhow::Result<()> {
+    let data = figment.data()?;
+    let Some(dict) = data.get(&Profile::Default) else {
+        return Ok(());
+    };
+
+    for (key, value) in dict {
+        let source = figment
+            .find_metadata(key)
+            .map(|m| match &m.source {
+                Some(s) => format!("{} {s}", m.name),
+                None => m.name.to_string(),
+            })
+            .unwrap_or_default();
+
+        if SECRET_KEYS.contains(&key.as_str()) {
+            tracing::info!(key, source, "config value <redacted>");
+        } else {
+            tracing::info!(
+                key,
+                source,
+                "config value {}",
+                redact(serde_json::to_value(value)?)
+            );
+        }
+    }
+
+    Ok(())
+}
+
+// redact replaces the values of nested secret keys, such as
+// the credentials of replicas.
+fn redact(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(map) => map
+            .into_iter()
+            .map(|(k, v)| match SECRET_KEYS.contains(&k.as_str()) {
+                true => (k, "<redacted>".into()),
+                false => (k, redact(v)),
+            })
+            .collect(),
+        serde_json::Value::Array(values) => values.into_iter().map(redact).collect(),
+        v => v,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(yaml: &str) -> Config {
+        let base = "base: https://opnsense.home.arpa\nkey: k\nsecret: s\n";
+        Figment::from(Yaml::string(&format!("{base}{yaml}")))
             .extract()
+            .unwrap()
+    }
+
+    fn filters(filters: &[&str]) -> Vec<String> {
+        filters.iter().map(|f| f.to_string()).collect()
+    }
+
+    #[test]
+    fn overlapping_filters_by_subdomain() {
+        assert_eq!(
+            overlapping_filters(&filters(&["home.arpa"]), &filters(&["lab.home.arpa"])),
+            Some(("home.arpa".to_owned(), "lab.home.arpa".to_owned()))
+        );
+        assert!(overlapping_filters(&filters(&["lab.arpa"]), &filters(&["home.arpa"])).is_none());
+        assert!(
+            overlapping_filters(&filters(&["home.arpa"]), &filters(&["myhome.arpa"])).is_none()
+        );
+    }
+
+    #[test]
+    fn overlapping_filters_with_dots() {
+        assert!(
+            overlapping_filters(&filters(&[".home.arpa"]), &filters(&["home.arpa."])).is_some()
+        );
+        assert!(
+            overlapping_filters(&filters(&[".home.arpa"]), &filters(&["lab.Home.arpa"])).is_some()
+        );
+        assert!(overlapping_filters(&filters(&[".lab.arpa"]), &filters(&[".home.arpa"])).is_none());
+    }
+
+    #[test]
+    fn empty_filters_overlap_everything() {
+        assert!(overlapping_filters(&[], &filters(&["home.arpa"])).is_some());
+        assert!(overlapping_filters(&filters(&["home.arpa"]), &[]).is_some());
+    }
+
+    #[test]
+    fn tenants_with_disjoint_zones() {
+        let config = config(
+            "gc: {enabled: true}\n\
+             tenants:\n  \
+               a: {domain_filters: [home.arpa], owner_id: a}\n  \
+               b: {domain_filters: [lab.arpa], owner_id: b}\n",
+        );
+
+        assert!(config.validate_tenants().is_ok());
+    }
+
+    #[test]
+    fn tenants_with_overlapping_zones() {
+        let config = config(
+            "tenants:\n  \
+               a: {domain_filters: [home.arpa]}\n  \
+               b: {domain_filters: [.lab.home.arpa]}\n",
+        );
+
+        assert!(config.validate_tenants().is_err());
+    }
+
+    #[test]
+    fn tenants_sharing_owner_id() {
+        // The owner ID is inherited from the top level.
+        let yaml = "owner_id: shared\n\
+                    tenants:\n  \
+                      a: {domain_filters: [home.arpa]}\n  \
+                      b: {domain_filters: [lab.arpa]}\n";
+
+        assert!(config(yaml).validate_tenants().is_ok());
+        assert!(config(&format!("gc: {{enabled: true}}\n{yaml}"))
+            .validate_tenants()
+            .is_err());
     }
 }
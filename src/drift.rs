@@ -0,0 +1,141 @@
+use crate::metrics::{DRIFTED_RECORDS, DRIFT_DETECTED, DRIFT_REPAIRED};
+use crate::opnsense::unbound::HostOverrideRecord;
+use crate::opnsense::Backend;
+use crate::replicate;
+use crate::state::{AppState, RecordCache, Recordkey, ZoneCache};
+use std::collections::HashMap;
+use std::time::Duration;
+
+// spawn periodically compares the host overrides last applied
+// through set_records with their current state in OPNsense, and
+// re-applies them when repair is enabled.
+pub fn spawn<R, Z>(state: AppState<R, Z>)
+where
+    R: RecordCache + Send + Sync + 'static,
+    Z: ZoneCache + Send + Sync + 'static,
+{
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(Duration::from_secs(state.config.drift.interval));
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+
+            if let Err(e) = run(&state).await {
+                tracing::error!("drift detection failed: {e:#}");
+            }
+            if let Err(e) = state.persist_record_cache().await {
+                tracing::error!("could not persist record cache: {e:#}");
+            }
+        }
+    });
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Drift {
+    Deleted,
+    Disabled,
+    Changed,
+}
+
+impl Drift {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Deleted => "deleted",
+            Self::Disabled => "disabled",
+            Self::Changed => "changed",
+        }
+    }
+}
+
+async fn run<R: RecordCache, Z: ZoneCache>(state: &AppState<R, Z>) -> anyhow::Result<()> {
+    let _batch = state.lock_batch().await;
+
+    let applied = state.desired.read().await.applied.clone();
+    if applied.is_empty() {
+        return Ok(());
+    }
+
+    let backend = state.opnsense.backend();
+
+    let actual = backend
+        .list_records()
+        .await?
+        .into_iter()
+        .flat_map(|r| Some((Recordkey::try_from(&r).ok()?, r)))
+        .collect::<HashMap<_, _>>();
+
+    let mut drifted = 0;
+    let mut repaired = 0;
+    let (mut updates, mut creates) = (vec![], vec![]);
+
+    // Host overrides repaired so far are replicated even if
+    // repairing the next one fails.
+    let result = async {
+        for (key, desired) in applied {
+            let drift = match actual.get(&key) {
+                None => Drift::Deleted,
+                Some(a) if a.enabled.trim() != desired.enabled.trim() => Drift::Disabled,
+                Some(a) if a.server != desired.server => Drift::Changed,
+                Some(_) => continue,
+            };
+
+            drifted += 1;
+            tracing::warn!(
+                fqdn = key.fqdn,
+                ?drift,
+                actual = ?actual.get(&key),
+                "host override drifted"
+            );
+            DRIFT_DETECTED.with_label_values(&[drift.as_str()]).inc();
+
+            if !state.config.drift.repair {
+                continue;
+            }
+
+            let record = match (drift, actual.get(&key)) {
+                (Drift::Deleted, _) | (_, None) => {
+                    let uuid = backend.add_record(&desired).await?;
+                    creates.push(desired.clone());
+                    HostOverrideRecord { uuid, ..desired }
+                }
+                (_, Some(a)) => {
+                    backend.set_record(&a.uuid, &desired).await?;
+                    updates.push(desired.clone());
+                    HostOverrideRecord {
+                        uuid: a.uuid.clone(),
+                        ..desired
+                    }
+                }
+            };
+
+            state
+                .record_cache
+                .write()
+                .await
+                .try_insert_record(&record)?;
+            state.desired.write().await.try_record_applied(&record)?;
+
+            tracing::info!(fqdn = key.fqdn, ?drift, "re-applied host override");
+            DRIFT_REPAIRED.with_label_values(&[drift.as_str()]).inc();
+            repaired += 1;
+        }
+
+        anyhow::Ok(())
+    }
+    .await;
+
+    replicate(state, &[], &updates, &creates).await;
+    result?;
+
+    DRIFTED_RECORDS
+        .with_label_values(&[state.config.tenant.as_deref().unwrap_or_default()])
+        .set(drifted - repaired);
+
+    if repaired > 0 {
+        state.invalidate_listing();
+        backend.apply().await?;
+    }
+
+    Ok(())
+}
@@ -66,18 +66,67 @@ impl Endpoint {
     ) -> Option<unbound::HostOverrideRecord> {
         let (host, domain) = self.get_host_and_domain(zones)?;
 
+        let enabled = match self.property(ENABLED).map(|v| (v, parse_enabled(v))) {
+            Some((_, Some(false))) => "0",
+            Some((value, None)) => {
+                tracing::warn!(
+                    value,
+                    dns_name = self.dns_name,
+                    "ignoring invalid enabled property"
+                );
+                "1"
+            }
+            _ => "1",
+        };
+
         Some(unbound::HostOverrideRecord {
             uuid: self.set_identifier.clone().unwrap_or_default(),
-            enabled: "1".to_string(),
+            enabled: enabled.to_string(),
             domain: domain.clone(),
             rr: self.record_type.clone(),
             server: self.targets[0].clone(),
             hostname: host.clone(),
             mx: "".to_string(),
             mxprio: "".to_string(),
-            description: "".to_string(),
+            description: self.property(DESCRIPTION).unwrap_or_default().to_string(),
         })
     }
+    // property returns the value of one of the webhook's
+    // properties, the canonical name taking precedence over the
+    // opnsense/ alias when both are given.
+    pub fn property(&self, key: &str) -> Option<&str> {
+        let canonical = ProviderSpecificProperty::new(key, "").name;
+        self.provider_specific
+            .iter()
+            .filter(|p| p.key() == Some(key))
+            .min_by_key(|p| p.name != canonical)
+            .map(|p| p.value.as_str())
+    }
+    // normalize_properties keeps the properties understood by the
+    // webhook, under their canonical name and value. Values the
+    // webhook writes by default are left out, as they are when
+    // records are listed, so external-dns sees no difference.
+    pub fn normalize_properties(self) -> Self {
+        let mut provider_specific = Vec::new();
+
+        for key in PROPERTIES {
+            let value = match (*key, self.property(key)) {
+                (_, None) | (MXPRIO, _) => continue,
+                (ENABLED, Some(v)) => match parse_enabled(v) {
+                    Some(false) => "false".to_owned(),
+                    _ => continue,
+                },
+                (_, Some(v)) if v.trim().is_empty() => continue,
+                (_, Some(v)) => v.to_owned(),
+            };
+            provider_specific.push(ProviderSpecificProperty::new(key, value));
+        }
+
+        Self {
+            provider_specific,
+            ..self
+        }
+    }
     fn get_host_and_domain<'a>(
         &self,
         zones: impl IntoIterator<Item = &'a String>,
@@ -117,10 +166,48 @@ impl IntoIterator for Targets {
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct ProviderSpecificProperty {
-    name: String,
-    value: String,
+    pub name: String,
+    pub value: String,
+}
+
+// Provider specific properties understood by the webhook. Set with
+// external-dns.alpha.kubernetes.io/webhook-opnsense-<key>
+// annotations, they are named webhook/opnsense-<key>; opnsense/<key>
+// is accepted as well, e.g. in DNSEndpoint resources.
+pub const DESCRIPTION: &str = "description";
+pub const ENABLED: &str = "enabled";
+// mxprio is accepted for compatibility but has no effect: only A
+// and AAAA host overrides are written, for which OPNsense ignores
+// the MX priority.
+pub const MXPRIO: &str = "mxprio";
+
+const PROPERTIES: &[&str] = &[DESCRIPTION, ENABLED, MXPRIO];
+
+impl ProviderSpecificProperty {
+    pub fn new(key: &str, value: impl Into<String>) -> Self {
+        Self {
+            name: format!("webhook/opnsense-{key}"),
+            value: value.into(),
+        }
+    }
+    // key returns which of the webhook's properties this is,
+    // whatever name it was given under.
+    pub fn key(&self) -> Option<&str> {
+        self.name
+            .strip_prefix("webhook/opnsense-")
+            .or_else(|| self.name.strip_prefix("opnsense/"))
+            .filter(|k| PROPERTIES.contains(k))
+    }
+}
+
+fn parse_enabled(value: &str) -> Option<bool> {
+    match value.trim().to_lowercase().as_str() {
+        "true" | "1" => Some(true),
+        "false" | "0" => Some(false),
+        _ => None,
+    }
 }
 
 #[derive(Deserialize, Debug)]
@@ -170,3 +257,95 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn endpoint(properties: &[(&str, &str)]) -> Endpoint {
+        Endpoint {
+            dns_name: "www.home.arpa".to_owned(),
+            targets: Targets(vec!["10.0.0.1".to_owned()]),
+            record_type: "A".to_owned(),
+            provider_specific: properties
+                .iter()
+                .map(|(name, value)| ProviderSpecificProperty {
+                    name: name.to_string(),
+                    value: value.to_string(),
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn canonical_name_takes_precedence() {
+        let ep = endpoint(&[
+            ("opnsense/description", "alias"),
+            ("webhook/opnsense-description", "canonical"),
+        ]);
+        assert_eq!(ep.property(DESCRIPTION), Some("canonical"));
+
+        let ep = endpoint(&[("opnsense/description", "alias")]);
+        assert_eq!(ep.property(DESCRIPTION), Some("alias"));
+    }
+
+    #[test]
+    fn unknown_properties_are_ignored() {
+        let ep = endpoint(&[("aws/weight", "1"), ("opnsense/ttl", "60")]);
+
+        assert_eq!(ep.property("ttl"), None);
+        assert!(ep.normalize_properties().provider_specific.is_empty());
+    }
+
+    #[test]
+    fn mxprio_is_accepted_without_effect() {
+        let zones = ["home.arpa".to_owned()];
+        let ep = endpoint(&[("webhook/opnsense-mxprio", "10")]);
+
+        assert_eq!(ep.property(MXPRIO), Some("10"));
+        assert_eq!(ep.get_record_for_zones(&zones).unwrap().mxprio, "");
+        assert!(ep.normalize_properties().provider_specific.is_empty());
+    }
+
+    #[test]
+    fn normalize_properties() {
+        let ep = endpoint(&[
+            ("opnsense/enabled", " False "),
+            ("opnsense/description", "alias"),
+            ("webhook/opnsense-description", "NAS"),
+        ])
+        .normalize_properties();
+
+        assert_eq!(
+            ep.provider_specific,
+            [
+                ProviderSpecificProperty::new(DESCRIPTION, "NAS"),
+                ProviderSpecificProperty::new(ENABLED, "false"),
+            ]
+        );
+    }
+
+    #[test]
+    fn normalize_properties_drops_defaults() {
+        let ep = endpoint(&[
+            ("webhook/opnsense-enabled", "true"),
+            ("webhook/opnsense-description", "  "),
+        ])
+        .normalize_properties();
+
+        assert!(ep.provider_specific.is_empty());
+    }
+
+    #[test]
+    fn record_from_properties() {
+        let zones = ["home.arpa".to_owned()];
+        let record = endpoint(&[("opnsense/enabled", "0"), ("opnsense/description", "NAS")])
+            .get_record_for_zones(&zones)
+            .unwrap();
+
+        assert_eq!(record.enabled, "0");
+        assert_eq!(record.description, "NAS");
+        assert_eq!(record.mxprio, "");
+    }
+}
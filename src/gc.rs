@@ -0,0 +1,173 @@
+use crate::cli::rr_type;
+use crate::config::DeleteMode;
+use crate::metrics::{GC_COLLECTED, GC_ORPHANS};
+use crate::opnsense::Backend;
+use crate::owner;
+use crate::state::{AppState, RecordCache, Recordkey, ZoneCache};
+use crate::{replicate, zones};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+// spawn periodically collects owned host overrides that are
+// missing from the desired state external-dns last sent, once they
+// have been missing for longer than the grace period.
+pub fn spawn<R, Z>(state: AppState<R, Z>)
+where
+    R: RecordCache + Send + Sync + 'static,
+    Z: ZoneCache + Send + Sync + 'static,
+{
+    tokio::spawn(async move {
+        let mut orphans = HashMap::new();
+        let mut ticker = tokio::time::interval(Duration::from_secs(state.config.gc.interval));
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+
+            if let Err(e) = run(&state, &mut orphans).await {
+                tracing::error!("garbage collection failed: {e:#}");
+            }
+            if let Err(e) = state.persist_record_cache().await {
+                tracing::error!("could not persist record cache: {e:#}");
+            }
+        }
+    });
+}
+
+async fn run<R: RecordCache, Z: ZoneCache>(
+    state: &AppState<R, Z>,
+    orphans: &mut HashMap<Recordkey, Instant>,
+) -> anyhow::Result<()> {
+    let gc = &state.config.gc;
+    let _batch = state.lock_batch().await;
+
+    let desired = state.desired.read().await;
+    let Some(seen_at) = desired.seen_at else {
+        tracing::debug!("no desired state received from external-dns yet, skipping gc");
+        return Ok(());
+    };
+    let desired_keys = desired.keys.clone();
+    drop(desired);
+
+    let zones = zones(state).await?;
+    let owner = state.config.owner_id.as_deref();
+    let backend = state.opnsense.backend();
+
+    // mode returns how orphans of the zone are collected.
+    let mode = |zone: &str| {
+        state
+            .config
+            .zone(zone)
+            .and_then(|z| z.delete_mode)
+            .unwrap_or(gc.mode)
+    };
+
+    let owned = backend
+        .list_records()
+        .await?
+        .into_iter()
+        .filter(|r| zones.contains(&r.domain))
+        .filter(|r| !state.config.zone(&r.domain).is_some_and(|z| z.read_only))
+        .filter(|r| state.config.manages_type(&r.domain, &r.rr))
+        .filter(|r| owner::is_owned_by(&r.description, owner))
+        .filter(|r| mode(&r.domain) == DeleteMode::Delete || r.enabled == "1");
+
+    let now = Instant::now();
+    let grace_period = Duration::from_secs(gc.grace_period);
+    let mut current = HashMap::new();
+    let mut collected = 0;
+    let (mut deletes, mut updates) = (vec![], vec![]);
+
+    // Host overrides collected so far are replicated even if
+    // collecting the next one fails.
+    let result = async {
+        for mut record in owned {
+            let Ok(key) = Recordkey::try_from(&record) else {
+                continue;
+            };
+            if desired_keys.contains(&key) {
+                continue;
+            }
+
+            let since = orphans.get(&key).copied().unwrap_or(now);
+            current.insert(key, since);
+
+            if now.duration_since(since) < grace_period {
+                continue;
+            }
+
+            let mode = mode(&record.domain);
+
+            if gc.dry_run {
+                tracing::info!(?record, ?mode, "would collect orphaned host override");
+                GC_COLLECTED.with_label_values(&["dry_run"]).inc();
+                continue;
+            }
+
+            match mode {
+                DeleteMode::Delete => {
+                    backend.delete_record(&record.uuid).await?;
+                    deletes.push(record.clone());
+                    state
+                        .record_cache
+                        .write()
+                        .await
+                        .try_remove_record(&record)?;
+                    state.desired.write().await.try_forget_applied(&record)?;
+                }
+                DeleteMode::Disable => {
+                    // Listed records carry the display form of their
+                    // type, which OPNsense does not accept back.
+                    record.rr = rr_type(&record).to_uppercase();
+                    record.enabled = "0".to_string();
+                    backend.set_record(&record.uuid, &record).await?;
+                    updates.push(record.clone());
+                    state
+                        .record_cache
+                        .write()
+                        .await
+                        .try_insert_record(&record)?;
+                    state.desired.write().await.try_forget_applied(&record)?;
+                }
+            }
+
+            tracing::info!(?record, ?mode, "collected orphaned host override");
+            GC_COLLECTED
+                .with_label_values(&[match mode {
+                    DeleteMode::Delete => "delete",
+                    DeleteMode::Disable => "disable",
+                }])
+                .inc();
+            collected += 1;
+        }
+
+        anyhow::Ok(())
+    }
+    .await;
+
+    replicate(state, &deletes, &updates, &[]).await;
+    result?;
+
+    if current.is_empty() {
+        tracing::debug!(desired_age = ?seen_at.elapsed(), "no orphaned host overrides");
+    } else {
+        tracing::info!(
+            orphans = current.len(),
+            collected,
+            desired_age = ?seen_at.elapsed(),
+            "garbage collection done"
+        );
+    }
+    GC_ORPHANS
+        .with_label_values(&[state.config.tenant.as_deref().unwrap_or_default()])
+        .set(current.len() as i64);
+
+    if collected > 0 {
+        state.invalidate_listing();
+        backend.apply().await?;
+    }
+
+    *orphans = current;
+
+    Ok(())
+}
@@ -1,20 +1,44 @@
+pub mod check;
+pub mod cli;
 pub mod config;
+mod drift;
 mod external_dns;
+mod gc;
+mod metrics;
+mod names;
 mod opnsense;
+mod owner;
+mod rewrite;
 mod state;
+mod targets;
+mod tls;
+pub mod transfer;
+mod watch;
 
+use anyhow::Context;
 use axum::{
-    extract::State,
+    extract::{Request, State},
     http::StatusCode,
+    response::IntoResponse,
     routing::{get, post},
     Json, Router,
 };
-use config::Config;
-use external_dns::{Changes, DomainFilter, Edns, Endpoint, Endpoints};
-use opnsense::Opnsense;
-use state::{AppState, DefaultRecordCache, DefaultZoneCache, RecordCache, ZoneCache};
-use std::sync::Arc;
+use axum_server::tls_rustls::RustlsConfig;
+use config::{BackendKind, Config, DeleteMode, RecordCacheConfig, ZoneDiscovery};
+use external_dns::{Changes, DomainFilter, Edns, Endpoint, Endpoints, ProviderSpecificProperty};
+use futures::StreamExt;
+use metrics::REPLICATION_FAILURES;
+use opnsense::unbound::HostOverrideRecord;
+use opnsense::{Backend, Opnsense};
+use state::{
+    AppState, DefaultRecordCache, FileRecordCache, Firewall, Listing, RecordCache, RecordEntry,
+    Recordkey, Replica, ZoneCache,
+};
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
+use tower::ServiceExt;
 use tower_http::trace::{self, TraceLayer};
 use tracing::instrument;
 
@@ -30,33 +54,167 @@ impl From<Config> for Server {
 
 impl Server {
     pub async fn serve(&self) -> anyhow::Result<()> {
-        let state = AppState {
-            opnsense: Opnsense::try_from(&self.config)?,
-            config: self.config.clone(),
-            record_cache: Arc::new(RwLock::new(DefaultRecordCache::new())),
-            zone_cache: Arc::new(RwLock::new(DefaultZoneCache::new())),
+        let firewall = Firewall::try_new(&self.config)?;
+        firewall.reload_on_change(&self.config);
+
+        let app = match self.config.tenants.is_empty() {
+            true => tenant_router(&self.config, &firewall).await?,
+            false => self.tenants_router(&firewall).await?,
+        }
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(trace::DefaultMakeSpan::new().level(tracing::Level::INFO))
+                .on_request(trace::DefaultOnRequest::new().level(tracing::Level::INFO))
+                .on_response(trace::DefaultOnResponse::new().level(tracing::Level::INFO)),
+        );
+
+        let Some(tls) = &self.config.tls else {
+            let listener = tokio::net::TcpListener::bind(&self.config.bind).await?;
+            tracing::info!("listening on {}", self.config.bind);
+
+            return Ok(axum::serve(listener, app).await?);
         };
 
-        let app = Router::new()
-            .route("/", get(negotiate))
-            .route("/healthz", get(healthz))
-            .route("/records", get(get_records).post(set_records))
-            .route("/adjustendpoints", post(adjust_records))
-            .with_state(state)
-            .layer(
-                TraceLayer::new_for_http()
-                    .make_span_with(trace::DefaultMakeSpan::new().level(tracing::Level::INFO))
-                    .on_request(trace::DefaultOnRequest::new().level(tracing::Level::INFO))
-                    .on_response(trace::DefaultOnResponse::new().level(tracing::Level::INFO)),
-            );
+        let rustls_config = RustlsConfig::from_config(tls::server_config(tls)?);
+
+        watch::spawn(
+            tls.files(),
+            Duration::from_secs(self.config.watch_interval),
+            {
+                let tls = tls.clone();
+                let rustls_config = rustls_config.clone();
+                move || match tls::server_config(&tls) {
+                    Ok(c) => {
+                        rustls_config.reload_from_config(c);
+                        tracing::info!("reloaded tls configuration");
+                    }
+                    Err(e) => tracing::error!("could not reload tls configuration: {e:#}"),
+                }
+            },
+        );
+
+        let listener = std::net::TcpListener::bind(&self.config.bind)?;
+        listener.set_nonblocking(true)?;
+        tracing::info!(
+            client_auth = tls.client_ca_file.is_some(),
+            "listening with tls on {}",
+            self.config.bind
+        );
+
+        Ok(axum_server::from_tcp_rustls(listener, rustls_config)
+            .serve(app.into_make_service())
+            .await?)
+    }
+}
 
-        let listener = tokio::net::TcpListener::bind(&self.config.bind).await?;
-        tracing::info!("listening on {}", self.config.bind);
+// Paths served at the top level or, with tenant_header, for the
+// tenant named in the header, which a tenant prefix would shadow.
+const RESERVED_PATHS: &[&str] = &["healthz", "metrics", "records", "adjustendpoints"];
 
-        Ok(axum::serve(listener, app).await?)
+impl Server {
+    // tenants_router serves every tenant under /<name>, and under
+    // / for requests carrying its name in tenant_header if set.
+    async fn tenants_router(&self, firewall: &Firewall) -> anyhow::Result<Router> {
+        let mut app = Router::new()
+            .route("/healthz", get(|| async {}))
+            .route("/metrics", get(metrics));
+        let mut routers = HashMap::new();
+        let mut cache_files = HashSet::new();
+
+        for (name, tenant) in &self.config.tenants {
+            if name.is_empty() || name.contains('/') || RESERVED_PATHS.contains(&name.as_str()) {
+                anyhow::bail!("invalid tenant name {name:?}");
+            }
+
+            let config = self.config.for_tenant(name, tenant);
+            if let RecordCacheConfig::File { path } = &config.record_cache {
+                if !cache_files.insert(path.clone()) {
+                    anyhow::bail!("tenant {name} shares record cache {}", path.display());
+                }
+            }
+
+            let router = tenant_router(&config, firewall)
+                .await
+                .with_context(|| format!("tenant {name}"))?;
+            tracing::info!(tenant = name, zones = ?config.domain_filters, "serving tenant");
+
+            app = app.nest(&format!("/{name}"), router.clone());
+            routers.insert(name.clone(), router);
+        }
+
+        let Some(header) = self.config.tenant_header.clone() else {
+            return Ok(app);
+        };
+
+        Ok(app.fallback(move |req: Request| {
+            let router = req
+                .headers()
+                .get(&header)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|t| routers.get(t))
+                .cloned();
+
+            async move {
+                match router {
+                    Some(router) => router.oneshot(req).await.into_response(),
+                    None => StatusCode::NOT_FOUND.into_response(),
+                }
+            }
+        }))
+    }
+}
+
+// tenant_router sets up the state of one external-dns instance,
+// warms up its record cache, starts its background jobs and
+// returns its routes.
+async fn tenant_router(config: &Config, firewall: &Firewall) -> anyhow::Result<Router> {
+    match &config.record_cache {
+        RecordCacheConfig::Memory => {
+            tenant_router_with(config, firewall, DefaultRecordCache::new()).await
+        }
+        RecordCacheConfig::File { path } => {
+            tenant_router_with(config, firewall, FileRecordCache::try_open(path)?).await
+        }
     }
 }
 
+async fn tenant_router_with<R>(
+    config: &Config,
+    firewall: &Firewall,
+    record_cache: R,
+) -> anyhow::Result<Router>
+where
+    R: RecordCache + Clone + Send + Sync + 'static,
+{
+    let state = AppState::try_with_firewall(config, firewall, record_cache)?;
+
+    match refresh_records(&state).await {
+        Ok(records) => tracing::info!(records = records.len(), "warmed up record cache"),
+        Err(e) => tracing::warn!("could not warm up record cache: {e:#}"),
+    }
+
+    if config.gc.enabled {
+        // Without an owner every host override in the managed zones
+        // counts as owned, and hand-made ones would be collected.
+        if config.owner_id.is_none() {
+            anyhow::bail!("gc.enabled requires owner_id to be set");
+        }
+        gc::spawn(state.clone());
+    }
+
+    if config.drift.enabled {
+        drift::spawn(state.clone());
+    }
+
+    Ok(Router::new()
+        .route("/", get(negotiate))
+        .route("/healthz", get(healthz))
+        .route("/metrics", get(metrics))
+        .route("/records", get(get_records).post(set_records))
+        .route("/adjustendpoints", post(adjust_records))
+        .with_state(state))
+}
+
 // negotiate retrieves filtered zones from Opnsense
 // and responds to external-dns with zone filters.
 #[instrument(skip(state))]
@@ -67,11 +225,16 @@ async fn negotiate<R: RecordCache, Z: ZoneCache>(
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    tracing::info!(?zones, "replying with filtered zones");
+    let mut filters = state.zone_cache.read().await.values();
+    filters.extend(names::external_domains(&zones, &state.config.name_rewrites));
 
-    Ok(Edns(DomainFilter {
-        filters: state.zone_cache.read().await.values(),
-    }))
+    tracing::info!(?filters, "replying with filtered zones");
+
+    Ok(Edns(DomainFilter { filters }))
+}
+
+async fn metrics() -> Result<String, StatusCode> {
+    metrics::render().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
 }
 
 #[instrument(skip(_state))]
@@ -79,39 +242,127 @@ async fn healthz<R: RecordCache, Z: ZoneCache>(State(_state): State<AppState<R,
 
 // Gets existing host overrides and filters by managed zones
 // Updates UUID map to map records to their UUID's
-// Returns "enabled" records as endpoints
+// Returns "enabled" records as endpoints, and disabled ones
+// external-dns still asks for
 #[instrument(skip(state))]
 async fn get_records<R: RecordCache, Z: ZoneCache>(
     State(state): State<AppState<R, Z>>,
 ) -> Result<Edns<Endpoints>, StatusCode> {
-    let list = state
-        .opnsense
-        .unbound()
-        .settings()
-        .search_host_override()
+    let records = list_records(&state)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let desired = state.desired.read().await.keys.clone();
 
-    let zones = zones(&state)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    let records = list.rows.into_iter().filter(|r| zones.contains(&r.domain));
+    Ok(Edns(Endpoints::from_iter(
+        records
+            .into_iter()
+            .filter(|r| {
+                r.enabled == "1" || Recordkey::try_from(r).is_ok_and(|key| desired.contains(&key))
+            })
+            .filter(|r| state.config.manages_type(&r.domain, &r.rr))
+            .map(|r| {
+                let provider_specific = properties(&state.config, &r);
+                let ep = Endpoint::from(rewrite::reverse(r, &state.config.rewrites));
+
+                Endpoint {
+                    dns_name: names::reverse(&ep.dns_name, &state.config.name_rewrites),
+                    provider_specific,
+                    ..ep
+                }
+            }),
+    )))
+}
 
-    let mut guard = state.record_cache.write().await;
+// properties returns the provider specific properties of record
+// departing from what the webhook writes by default. Descriptions
+// are listed without owner marker, unless rendered from the zone's
+// template.
+fn properties(config: &Config, record: &HostOverrideRecord) -> Vec<ProviderSpecificProperty> {
+    let mut properties = Vec::new();
+
+    let description = owner::strip(&record.description);
+    let templated = config
+        .zone(&record.domain)
+        .and_then(|z| z.description.as_ref())
+        .map(|t| describe(t, record));
+    if !description.is_empty() && templated.as_deref() != Some(description) {
+        properties.push(ProviderSpecificProperty::new(
+            external_dns::DESCRIPTION,
+            description,
+        ));
+    }
+    if record.enabled.trim() != "1" {
+        properties.push(ProviderSpecificProperty::new(
+            external_dns::ENABLED,
+            "false",
+        ));
+    }
 
-    guard.clear();
+    properties
+}
 
-    for r in records.clone() {
-        guard
-            .try_insert_record(&r)
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+// list_records returns the managed host overrides, sharing a
+// single refresh between concurrent requests. Listings stay valid
+// for listing_cache_ttl seconds unless records were changed since.
+async fn list_records<R: RecordCache, Z: ZoneCache>(
+    state: &AppState<R, Z>,
+) -> anyhow::Result<Vec<HostOverrideRecord>> {
+    let arrived_at = Instant::now();
+    let ttl = Duration::from_secs(state.config.listing_cache_ttl);
+
+    let mut listing = state.listing.lock().await;
+
+    if let Some(l) = listing.as_ref() {
+        if l.generation == state.listing_generation()
+            && (l.fetched_at >= arrived_at || l.fetched_at.elapsed() < ttl)
+        {
+            tracing::debug!(age = ?l.fetched_at.elapsed(), "serving cached host override listing");
+            return Ok(l.records.clone());
+        }
     }
 
-    drop(guard);
+    let generation = state.listing_generation();
+    let records = refresh_records(state).await?;
 
-    Ok(Edns(Endpoints::from_iter(
-        records.filter(|r| r.enabled == "1").map(Into::into),
-    )))
+    *listing = Some(Listing {
+        records: records.clone(),
+        fetched_at: Instant::now(),
+        generation,
+    });
+
+    Ok(records)
+}
+
+// refresh_records lists the host overrides in the managed
+// zones and rebuilds the record cache from them. Host overrides
+// the webhook cannot represent (e.g. MX records added by hand)
+// are skipped.
+async fn refresh_records<R: RecordCache, Z: ZoneCache>(
+    state: &AppState<R, Z>,
+) -> anyhow::Result<Vec<HostOverrideRecord>> {
+    let _batch = state.lock_batch().await;
+
+    let list = state.opnsense.backend().list_records().await?;
+
+    let zones = zones(state).await?;
+    let records = list
+        .into_iter()
+        .filter(|r| zones.contains(&r.domain))
+        .filter(
+            |r| match (Recordkey::try_from(r), RecordEntry::try_from(r)) {
+                (Ok(_), Ok(_)) => true,
+                (Err(e), _) | (_, Err(e)) => {
+                    tracing::warn!(record = ?r, "skipping unsupported host override: {e}");
+                    false
+                }
+            },
+        )
+        .collect::<Vec<_>>();
+
+    state.record_cache.write().await.try_replace_all(&records)?;
+    state.persist_record_cache().await?;
+
+    Ok(records)
 }
 
 #[instrument(skip(state, changes))]
@@ -119,18 +370,35 @@ async fn set_records<R: RecordCache, Z: ZoneCache>(
     State(state): State<AppState<R, Z>>,
     Json(changes): Json<Changes>,
 ) -> Result<StatusCode, StatusCode> {
+    let _batch = state.lock_batch().await;
+
     let (creates, updates, deletes) = process_changes(&state, changes)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
+    let replicated =
+        (!state.replicas.is_empty()).then(|| (deletes.clone(), updates.clone(), creates.clone()));
+
+    // Deletes go first so a record deleted and created again
+    // under the same name in one batch doesn't collide.
     let results = [
-        create_records(&state, creates).await,
-        update_records(&state, updates).await,
         delete_records(&state, deletes).await,
+        update_records(&state, updates).await,
+        create_records(&state, creates).await,
     ]
     .into_iter()
     .collect::<Result<Vec<_>, _>>();
 
+    if let Some((deletes, updates, creates)) = replicated {
+        replicate(&state, &deletes, &updates, &creates).await;
+    }
+
+    state.invalidate_listing();
+
+    if let Err(e) = state.persist_record_cache().await {
+        tracing::error!("could not persist record cache: {e:#}");
+    }
+
     match results {
         Ok(res) => {
             for out in &res {
@@ -138,7 +406,10 @@ async fn set_records<R: RecordCache, Z: ZoneCache>(
             }
 
             if res.iter().any(|o| o.requires_restart()) {
-                let _ = state.opnsense.unbound().service().restart().await;
+                let backend = state.opnsense.backend();
+                if let Err(e) = backend.apply().await {
+                    tracing::warn!("could not apply changes to {}: {e}", backend.name());
+                }
             }
 
             Ok(StatusCode::NO_CONTENT)
@@ -160,28 +431,89 @@ async fn process_changes<R: RecordCache, Z: ZoneCache>(
     Vec<opnsense::unbound::HostOverrideRecord>,
 )> {
     let zones = zones(state).await?;
+    let config = &state.config;
+
+    // to_record maps an endpoint to the host override it stands
+    // for, if the webhook may write it.
+    let to_record = |ep: Endpoint, filter_targets: bool| {
+        let ep = names::forward_endpoint(ep, &config.name_rewrites);
+        let ep = match filter_targets {
+            true => {
+                let zone = ep.dns_name.split_once('.').unwrap_or_default().1;
+                targets::filter(ep.clone(), config.targets_for(zone))?
+            }
+            false => ep,
+        };
+        let record = ep.get_record_for_zones(&zones)?;
+
+        match config.zone(&record.domain) {
+            Some(z) if z.read_only => {
+                tracing::warn!(?record, "ignoring change in read-only zone");
+                None
+            }
+            _ if !config.manages_type(&record.domain, &record.rr) => {
+                tracing::warn!(
+                    ?record,
+                    "ignoring change of a record type not managed in zone"
+                );
+                None
+            }
+            _ => Some(record),
+        }
+    };
+    let tag = |mut record: opnsense::unbound::HostOverrideRecord| {
+        if let Some(template) = config
+            .zone(&record.domain)
+            .and_then(|z| z.description.as_ref())
+            .filter(|_| record.description.is_empty())
+        {
+            record.description = describe(template, &record);
+        }
+        record.description = owner::tag(&record.description, config.owner_id.as_deref());
+        rewrite::forward(record, &config.rewrites)
+    };
 
     let mut creates: Vec<opnsense::unbound::HostOverrideRecord> = vec![];
     let mut updates: Vec<opnsense::unbound::HostOverrideRecord> = changes
         .update_new
         .into_iter()
-        .flat_map(|ep| ep.get_record_for_zones(&zones))
+        .flat_map(|ep| to_record(ep, true))
+        .map(tag)
         .collect();
-    let deletes: Vec<opnsense::unbound::HostOverrideRecord> = changes
+    let mut deletes: Vec<opnsense::unbound::HostOverrideRecord> = vec![];
+
+    for mut record in changes
         .delete
         .into_iter()
-        .flat_map(|ep| ep.get_record_for_zones(&zones))
-        .collect();
+        .flat_map(|ep| to_record(ep, false))
+    {
+        match config.zone(&record.domain).and_then(|z| z.delete_mode) {
+            Some(DeleteMode::Disable) => {
+                record.enabled = "0".to_owned();
+                let record = tag(record);
+                // Deleting a missing record is a no-op, it must not
+                // come back as a disabled host override.
+                match get_record_entry(&state.opnsense, &state.record_cache, &record).await? {
+                    Some(_) => updates.push(record),
+                    None => tracing::debug!(?record, "host override to disable not found"),
+                }
+            }
+            _ => deletes.push(rewrite::forward(record, &config.rewrites)),
+        }
+    }
 
     let guard = state.record_cache.read().await;
 
     for record in changes
         .create
         .into_iter()
-        .flat_map(|ep| ep.get_record_for_zones(&zones))
+        .flat_map(|ep| to_record(ep, true))
+        .map(tag)
     {
         match guard.try_get_record(&record)? {
-            Some(e) if e.enabled => continue,
+            Some(e) if e.enabled == (record.enabled == "1") && e.server == record.server => {
+                continue
+            }
             Some(_) => updates.push(record),
             None => creates.push(record),
         }
@@ -193,100 +525,309 @@ async fn process_changes<R: RecordCache, Z: ZoneCache>(
 #[instrument(skip(state, creates))]
 async fn create_records<R: RecordCache, Z: ZoneCache>(
     state: &AppState<R, Z>,
-    creates: impl IntoIterator<Item = opnsense::unbound::HostOverrideRecord>,
+    creates: impl IntoIterator<Item = HostOverrideRecord>,
 ) -> anyhow::Result<Output> {
-    let mut output = Output::new(Operation::Create);
-
-    for mut record in creates {
-        output.records_requested += 1;
-
-        let res = state
-            .opnsense
-            .unbound()
-            .settings()
-            .add_host_override(&record)
-            .await?;
-
-        tracing::debug!(?record, "added host override");
+    for_each_record(state, Operation::Create, creates, |r| {
+        create_record(state, r)
+    })
+    .await
+}
 
-        output.records_processed += 1;
+async fn create_record<R: RecordCache, Z: ZoneCache>(
+    state: &AppState<R, Z>,
+    mut record: HostOverrideRecord,
+) -> anyhow::Result<bool> {
+    record.uuid = state.opnsense.backend().add_record(&record).await?;
 
-        record.uuid = res.uuid;
+    tracing::debug!(?record, "added host override");
 
-        state
-            .record_cache
-            .write()
-            .await
-            .try_insert_record(&record)?;
-    }
+    state
+        .record_cache
+        .write()
+        .await
+        .try_insert_record(&record)?;
+    state.desired.write().await.try_record_applied(&record)?;
 
-    Ok(output)
+    Ok(true)
 }
 
 #[instrument(skip(state, updates))]
 async fn update_records<R: RecordCache, Z: ZoneCache>(
     state: &AppState<R, Z>,
-    updates: impl IntoIterator<Item = opnsense::unbound::HostOverrideRecord>,
+    updates: impl IntoIterator<Item = HostOverrideRecord>,
 ) -> anyhow::Result<Output> {
-    let mut output = Output::new(Operation::Update);
-
-    let guard = state.record_cache.read().await;
-
-    for record in updates {
-        output.records_requested += 1;
+    for_each_record(state, Operation::Update, updates, |r| {
+        update_record(state, r)
+    })
+    .await
+}
 
-        let entry = guard.try_get_record(&record)?.ok_or(anyhow::anyhow!(
-            "could not find uuid in map: {}.{}",
-            &record.hostname,
-            &record.domain
-        ))?;
+async fn update_record<R: RecordCache, Z: ZoneCache>(
+    state: &AppState<R, Z>,
+    record: HostOverrideRecord,
+) -> anyhow::Result<bool> {
+    let Some(entry) = get_record_entry(&state.opnsense, &state.record_cache, &record).await? else {
+        tracing::warn!(?record, "host override to update not found, creating it");
 
-        tracing::debug!(?entry, "updating host override");
+        return create_record(state, record).await;
+    };
 
-        output.records_processed += 1;
+    tracing::debug!(?entry, "updating host override");
 
-        state
-            .opnsense
-            .unbound()
-            .settings()
-            .set_host_override(&entry.uuid, &record)
-            .await?;
-    }
+    state
+        .opnsense
+        .backend()
+        .set_record(&entry.uuid, &record)
+        .await?;
+
+    let record = HostOverrideRecord {
+        uuid: entry.uuid,
+        ..record
+    };
+
+    state
+        .record_cache
+        .write()
+        .await
+        .try_insert_record(&record)?;
+    state.desired.write().await.try_record_applied(&record)?;
 
-    Ok(output)
+    Ok(true)
 }
 
 #[instrument(skip(state, deletes))]
 async fn delete_records<R: RecordCache, Z: ZoneCache>(
     state: &AppState<R, Z>,
-    deletes: impl IntoIterator<Item = opnsense::unbound::HostOverrideRecord>,
+    deletes: impl IntoIterator<Item = HostOverrideRecord>,
 ) -> anyhow::Result<Output> {
-    let mut output = Output::new(Operation::Delete);
+    for_each_record(state, Operation::Delete, deletes, |r| {
+        delete_record(state, r)
+    })
+    .await
+}
+
+async fn delete_record<R: RecordCache, Z: ZoneCache>(
+    state: &AppState<R, Z>,
+    record: HostOverrideRecord,
+) -> anyhow::Result<bool> {
+    let Some(entry) = get_record_entry(&state.opnsense, &state.record_cache, &record).await? else {
+        tracing::info!(?record, "host override to delete does not exist");
 
-    let mut guard = state.record_cache.write().await;
+        state.desired.write().await.try_forget_applied(&record)?;
+        return Ok(false);
+    };
 
-    for record in deletes.into_iter() {
+    tracing::debug!(?entry, "deleting host override");
+
+    state.opnsense.backend().delete_record(&entry.uuid).await?;
+
+    state
+        .record_cache
+        .write()
+        .await
+        .try_remove_record(&record)?;
+    state.desired.write().await.try_forget_applied(&record)?;
+
+    Ok(true)
+}
+
+// for_each_record applies op to every record, with at most
+// `concurrency` OPNsense calls in flight. All records are attempted
+// even if some fail; the first error is returned.
+async fn for_each_record<R, Z, F, Fut>(
+    state: &AppState<R, Z>,
+    op: Operation,
+    records: impl IntoIterator<Item = HostOverrideRecord>,
+    f: F,
+) -> anyhow::Result<Output>
+where
+    R: RecordCache,
+    Z: ZoneCache,
+    F: Fn(HostOverrideRecord) -> Fut,
+    Fut: Future<Output = anyhow::Result<bool>>,
+{
+    let mut output = Output::new(op);
+
+    let results = futures::stream::iter(records)
+        .map(f)
+        .buffer_unordered(state.config.concurrency.max(1))
+        .collect::<Vec<_>>()
+        .await;
+
+    let mut error = None;
+
+    for res in results {
         output.records_requested += 1;
 
-        let entry = guard
-            .try_get_record(&record)?
-            .ok_or(anyhow::anyhow!("could not find uuid in map: {:?}", &record))?;
+        match res {
+            Ok(processed) => output.records_processed += u64::from(processed),
+            Err(e) => {
+                tracing::error!("{}: {e:#}", output.operation);
+                error.get_or_insert(e);
+            }
+        }
+    }
 
-        tracing::debug!(?entry, "deleting host override");
+    match error {
+        Some(e) => Err(e),
+        None => Ok(output),
+    }
+}
 
-        output.records_processed += 1;
+// describe renders a description template for the record. Listed
+// records carry the display form of their type, e.g. "A (IPv4
+// address)", of which only the type itself is used.
+fn describe(template: &str, record: &HostOverrideRecord) -> String {
+    template
+        .replace("{fqdn}", &format!("{}.{}", record.hostname, record.domain))
+        .replace("{hostname}", &record.hostname)
+        .replace("{zone}", &record.domain)
+        .replace("{type}", cli::rr_type(record))
+}
 
-        state
-            .opnsense
-            .unbound()
-            .settings()
-            .delete_host_override(&entry.uuid)
-            .await?;
+// replicate applies a batch to the replicas managing its records.
+// Failures are reported per replica without failing the batch, as
+// external-dns plans its changes against the primary instance.
+async fn replicate<R: RecordCache, Z: ZoneCache>(
+    state: &AppState<R, Z>,
+    deletes: &[HostOverrideRecord],
+    updates: &[HostOverrideRecord],
+    creates: &[HostOverrideRecord],
+) {
+    futures::future::join_all(state.replicas.iter().map(|replica| async move {
+        if let Err(e) = replicate_to(state, replica, deletes, updates, creates).await {
+            tracing::error!(
+                instance = replica.name,
+                "could not replicate changes: {e:#}"
+            );
+            REPLICATION_FAILURES
+                .with_label_values(&[&replica.name])
+                .inc();
+        }
+    }))
+    .await;
+}
+
+async fn replicate_to<R: RecordCache, Z: ZoneCache>(
+    state: &AppState<R, Z>,
+    replica: &Replica,
+    deletes: &[HostOverrideRecord],
+    updates: &[HostOverrideRecord],
+    creates: &[HostOverrideRecord],
+) -> anyhow::Result<()> {
+    let managed = |records: &[HostOverrideRecord]| {
+        records
+            .iter()
+            .filter(|r| replica.manages(r))
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+
+    let results = [
+        for_each_record(state, Operation::Delete, managed(deletes), |r| {
+            delete_replica_record(replica, r)
+        })
+        .await?,
+        for_each_record(state, Operation::Update, managed(updates), |r| {
+            write_replica_record(replica, r)
+        })
+        .await?,
+        for_each_record(state, Operation::Create, managed(creates), |r| {
+            write_replica_record(replica, r)
+        })
+        .await?,
+    ];
+
+    for out in &results {
+        tracing::info!(instance = replica.name, "{}", out);
+    }
 
-        guard.try_remove_record(&record)?;
+    if results.iter().any(|o| o.requires_restart()) {
+        replica.opnsense.backend().apply().await?;
     }
 
-    Ok(output)
+    Ok(())
+}
+
+// write_replica_record creates or updates the record on the
+// replica, whichever applies, as replicas may have missed earlier
+// batches.
+async fn write_replica_record(
+    replica: &Replica,
+    mut record: HostOverrideRecord,
+) -> anyhow::Result<bool> {
+    let backend = replica.opnsense.backend();
+
+    match get_record_entry(&replica.opnsense, &replica.record_cache, &record).await? {
+        Some(entry) => {
+            backend.set_record(&entry.uuid, &record).await?;
+            record.uuid = entry.uuid;
+        }
+        None => record.uuid = backend.add_record(&record).await?,
+    }
+
+    replica
+        .record_cache
+        .write()
+        .await
+        .try_insert_record(&record)?;
+
+    Ok(true)
+}
+
+async fn delete_replica_record(
+    replica: &Replica,
+    record: HostOverrideRecord,
+) -> anyhow::Result<bool> {
+    let Some(entry) = get_record_entry(&replica.opnsense, &replica.record_cache, &record).await?
+    else {
+        return Ok(false);
+    };
+
+    replica
+        .opnsense
+        .backend()
+        .delete_record(&entry.uuid)
+        .await?;
+    replica
+        .record_cache
+        .write()
+        .await
+        .try_remove_record(&record)?;
+
+    Ok(true)
+}
+
+// get_record_entry returns the cached entry for a record. On a
+// cache miss, e.g. when external-dns changes records before listing
+// them after a restart, it searches OPNsense for the host override
+// and caches it.
+async fn get_record_entry(
+    opnsense: &Opnsense,
+    record_cache: &RwLock<impl RecordCache>,
+    record: &HostOverrideRecord,
+) -> anyhow::Result<Option<RecordEntry>> {
+    if let Some(entry) = record_cache.read().await.try_get_record(record)? {
+        return Ok(Some(entry));
+    }
+
+    tracing::debug!(?record, "record cache miss, searching opnsense");
+
+    let key = Recordkey::try_from(record)?;
+    let found = opnsense
+        .backend()
+        .search_records(&record.hostname)
+        .await?
+        .into_iter()
+        .find(|r| Recordkey::try_from(r).is_ok_and(|k| k == key));
+
+    let Some(found) = found else {
+        return Ok(None);
+    };
+
+    record_cache.write().await.try_insert_record(&found)?;
+
+    Ok(Some(RecordEntry::try_from(&found)?))
 }
 
 struct Output {
@@ -336,59 +877,163 @@ impl std::fmt::Display for Operation {
     }
 }
 
-#[instrument(skip(_state))]
+// adjust_records receives every endpoint external-dns wants,
+// which is remembered as the desired state for garbage collection.
+#[instrument(skip(state))]
 async fn adjust_records<R: RecordCache, Z: ZoneCache>(
-    State(_state): State<AppState<R, Z>>,
+    State(state): State<AppState<R, Z>>,
     Json(endpoints): Json<Endpoints>,
 ) -> Result<Edns<Endpoints>, StatusCode> {
-    Ok(Edns(
-        endpoints
-            .into_iter()
-            .filter(|ep| ["A", "AAAA"].contains(&ep.record_type.as_str()))
-            .map(|ep| Endpoint {
-                record_ttl: None,
-                targets: (&ep.targets[0]).into(),
-                ..ep.clone()
-            })
-            .collect(),
-    ))
+    let endpoints = endpoints
+        .into_iter()
+        .filter(|ep| ["A", "AAAA"].contains(&ep.record_type.as_str()))
+        .flat_map(|ep| {
+            let name = names::forward(&ep.dns_name, &state.config.name_rewrites);
+            let zone = name.split_once('.').unwrap_or_default().1;
+
+            match state.config.manages_type(zone, &ep.record_type) {
+                true => targets::filter(ep, state.config.targets_for(zone)),
+                false => None,
+            }
+        })
+        .map(|ep| Endpoint {
+            record_ttl: None,
+            targets: (&ep.targets[0]).into(),
+            ..ep.normalize_properties()
+        })
+        .map(|mut ep| {
+            // Dnsmasq host entries cannot be disabled.
+            if state.config.backend == BackendKind::Dnsmasq
+                && ep.property(external_dns::ENABLED).is_some()
+            {
+                tracing::warn!(
+                    dns_name = ep.dns_name,
+                    "ignoring enabled property, not supported by the dnsmasq backend"
+                );
+                ep.provider_specific
+                    .retain(|p| p.key() != Some(external_dns::ENABLED));
+            }
+            ep
+        })
+        .collect::<Endpoints>();
+
+    // The desired state is compared with host overrides, which
+    // carry the rewritten names.
+    state.desired.write().await.replace(
+        &endpoints
+            .0
+            .iter()
+            .map(|ep| names::forward_endpoint(ep.clone(), &state.config.name_rewrites))
+            .collect::<Vec<_>>(),
+    );
+
+    Ok(Edns(endpoints))
 }
 
-// zones retrieves zones from Opnsense, filters,
-// and caches them. Zones are returned from cache
-// on subsequent calls.
+// zones retrieves zones from Opnsense and/or the static
+// zone list, filters, and caches them. Zones are returned
+// from cache on subsequent calls.
 #[instrument(skip(state))]
 async fn zones<R: RecordCache, Z: ZoneCache>(
     state: &AppState<R, Z>,
 ) -> anyhow::Result<Vec<String>> {
-    let filters = &state.config.domain_filters;
+    let config = &state.config;
     let mut guard = state.zone_cache.write().await;
     let zones = guard.values();
     if !zones.is_empty() {
         return Ok(zones);
     }
 
-    let zones = state
-        .opnsense
-        .unbound()
-        .diagnostics()
-        .list_local_zones()
-        .await?
-        .data
+    // Discovery failing is not fatal in union mode, the static
+    // zones are served uncached and discovery is retried on the
+    // next call.
+    let mut cache = true;
+    let local = match config.zone_discovery {
+        ZoneDiscovery::Discover => Some(state.opnsense.backend().list_zones().await?),
+        ZoneDiscovery::Static => None,
+        ZoneDiscovery::Union => match state.opnsense.backend().list_zones().await {
+            Ok(local) => Some(local),
+            Err(e) => {
+                tracing::warn!("could not discover zones, using static zones only: {e:#}");
+                cache = false;
+                None
+            }
+        },
+    };
+
+    if let Some(local) = &local {
+        for zone in unserved_zones(config, local) {
+            tracing::warn!(zone, "static zone is not served by OPNsense");
+        }
+    }
+
+    let zones = managed_zones(config, local.as_deref());
+
+    if cache {
+        guard.extend(zones.clone());
+    }
+
+    Ok(zones)
+}
+
+// filter_zones keeps the local zones of an allowed type
+// matching the configured domain filters.
+fn filter_zones(zones: &[opnsense::unbound::Zone], filters: &[String]) -> Vec<String> {
+    zones
         .iter()
         .filter_map(|z| z.is_allowed_type().then_some(&z.zone))
         .flat_map(|z| z.strip_suffix('.'))
-        .filter(|z| {
-            filters.is_empty()
-                || filters
-                    .iter()
-                    .map(|f| f.strip_prefix('.').unwrap_or(f))
-                    .any(|f| z.ends_with(f))
-        })
+        .filter(|z| matches_filters(z, filters))
         .map(Into::into)
-        .collect::<Vec<String>>();
+        .collect()
+}
 
-    guard.extend(zones.clone());
+fn matches_filters(zone: &str, filters: &[String]) -> bool {
+    filters.is_empty()
+        || filters
+            .iter()
+            .map(|f| f.strip_prefix('.').unwrap_or(f))
+            .any(|f| zone.ends_with(f))
+}
 
-    Ok(zones)
+// managed_zones returns the zones matching the domain filters
+// among the static zones and the discovered local zones, as
+// selected by zone_discovery.
+fn managed_zones(config: &Config, local: Option<&[opnsense::unbound::Zone]>) -> Vec<String> {
+    let static_zones = match config.zone_discovery {
+        ZoneDiscovery::Discover => &[][..],
+        _ => &config.static_zones[..],
+    };
+    let discovered = match (config.zone_discovery, local) {
+        (ZoneDiscovery::Static, _) | (_, None) => Vec::new(),
+        (_, Some(local)) => filter_zones(local, &config.domain_filters),
+    };
+
+    let mut zones: Vec<String> = Vec::new();
+    for zone in static_zones
+        .iter()
+        .map(|z| z.trim_matches('.'))
+        .filter(|z| matches_filters(z, &config.domain_filters))
+        .chain(discovered.iter().map(String::as_str))
+    {
+        if !zones.iter().any(|z| z == zone) {
+            zones.push(zone.to_owned());
+        }
+    }
+
+    zones
+}
+
+// unserved_zones lists the static zones missing from the local
+// zones of an allowed type, for which host overrides would not
+// be answered.
+fn unserved_zones<'a>(config: &'a Config, local: &[opnsense::unbound::Zone]) -> Vec<&'a str> {
+    let served = filter_zones(local, &[]);
+
+    config
+        .static_zones
+        .iter()
+        .map(|z| z.trim_matches('.'))
+        .filter(|z| !served.iter().any(|s| s == z))
+        .collect()
 }
@@ -1,11 +1,132 @@
-use opnsense_unbound_external_dns_webhook::{config::Config, Server};
+use clap::{Parser, Subcommand};
+use opnsense_unbound_external_dns_webhook::{
+    check,
+    cli::{self, OutputFormat},
+    config::Config,
+    transfer::{self, TransferFormat},
+    Server,
+};
+use std::path::PathBuf;
+
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    /// Configuration files (YAML, TOML or JSON), merged in order
+    #[arg(
+        short,
+        long = "config",
+        env = "OPNSENSE_CONFIG",
+        value_delimiter = ',',
+        global = true
+    )]
+    config: Vec<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Serve the external-dns webhook (default)
+    Serve,
+    /// Validate the configuration and test connectivity to OPNsense
+    Check,
+    /// List managed host overrides
+    List {
+        /// Only list host overrides in this zone
+        #[arg(long)]
+        zone: Option<String>,
+        #[arg(short, long, value_enum, default_value_t)]
+        output: OutputFormat,
+    },
+    /// Show the managed host overrides for a name
+    Get {
+        fqdn: String,
+        /// Record type (A or AAAA), all types if omitted
+        #[arg(short = 't', long = "type")]
+        record_type: Option<String>,
+        #[arg(short, long, value_enum, default_value_t)]
+        output: OutputFormat,
+    },
+    /// Create or update a host override
+    Set {
+        fqdn: String,
+        target: String,
+        /// Record type (A or AAAA), guessed from the target if omitted
+        #[arg(short = 't', long = "type")]
+        record_type: Option<String>,
+    },
+    /// Delete the host overrides for a name
+    Delete {
+        fqdn: String,
+        /// Record type (A or AAAA), all types if omitted
+        #[arg(short = 't', long = "type")]
+        record_type: Option<String>,
+    },
+    /// List the managed zones
+    Zones {
+        #[arg(short, long, value_enum, default_value_t)]
+        output: OutputFormat,
+    },
+    /// Export managed host overrides as a zone file fragment or JSON
+    Export {
+        /// Only export host overrides in this zone
+        #[arg(long)]
+        zone: Option<String>,
+        #[arg(short, long, value_enum, default_value = "zone")]
+        format: TransferFormat,
+        /// Write to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Create or update host overrides from an exported file
+    Import {
+        file: PathBuf,
+        /// Format of the file, guessed from its extension if omitted
+        #[arg(short, long, value_enum)]
+        format: Option<TransferFormat>,
+        /// Only print the changes that would be made
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+
     let collector = tracing_subscriber::fmt()
         .with_writer(std::io::stderr)
         .finish();
     tracing::subscriber::set_global_default(collector)?;
 
-    Server::from(Config::try_from_env()?).serve().await
+    let config = Config::load(&cli.config)?;
+
+    match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => Server::from(config).serve().await,
+        Command::Check => check::run(&config).await,
+        Command::List { zone, output } => cli::list(&config, zone, output).await,
+        Command::Get {
+            fqdn,
+            record_type,
+            output,
+        } => cli::get(&config, &fqdn, record_type, output).await,
+        Command::Set {
+            fqdn,
+            target,
+            record_type,
+        } => cli::set(&config, &fqdn, &target, record_type).await,
+        Command::Delete { fqdn, record_type } => cli::delete(&config, &fqdn, record_type).await,
+        Command::Zones { output } => cli::list_zones(&config, output).await,
+        Command::Export {
+            zone,
+            format,
+            output,
+        } => transfer::export(&config, zone, format, output.as_deref()).await,
+        Command::Import {
+            file,
+            format,
+            dry_run,
+        } => transfer::import(&config, &file, format, dry_run).await,
+    }
 }
@@ -0,0 +1,89 @@
+use prometheus::{
+    register_histogram, register_int_counter_vec, register_int_gauge, register_int_gauge_vec,
+    Histogram, IntCounterVec, IntGauge, IntGaugeVec,
+};
+use std::sync::LazyLock;
+
+pub static GC_ORPHANS: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+    register_int_gauge_vec!(
+        "webhook_gc_orphans",
+        "Owned host overrides no longer desired by external-dns, by tenant",
+        &["tenant"]
+    )
+    .unwrap()
+});
+
+pub static GC_COLLECTED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "webhook_gc_collected_total",
+        "Orphaned host overrides collected, by action",
+        &["action"]
+    )
+    .unwrap()
+});
+
+pub static DRIFTED_RECORDS: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+    register_int_gauge_vec!(
+        "webhook_drifted_records",
+        "Host overrides differing from what the webhook last applied, by tenant",
+        &["tenant"]
+    )
+    .unwrap()
+});
+
+pub static DRIFT_DETECTED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "webhook_drift_detected_total",
+        "Drifted host overrides detected, by kind of drift",
+        &["kind"]
+    )
+    .unwrap()
+});
+
+pub static DRIFT_REPAIRED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "webhook_drift_repaired_total",
+        "Drifted host overrides re-applied, by kind of drift",
+        &["kind"]
+    )
+    .unwrap()
+});
+
+pub static BATCHES_QUEUED: LazyLock<IntGauge> = LazyLock::new(|| {
+    register_int_gauge!(
+        "webhook_batches_queued",
+        "Change batches and cache rebuilds waiting for their turn"
+    )
+    .unwrap()
+});
+
+pub static BATCH_WAIT_SECONDS: LazyLock<Histogram> = LazyLock::new(|| {
+    register_histogram!(
+        "webhook_batch_wait_seconds",
+        "Time change batches and cache rebuilds waited for their turn"
+    )
+    .unwrap()
+});
+
+pub static RATE_LIMIT_WAIT_SECONDS: LazyLock<Histogram> = LazyLock::new(|| {
+    register_histogram!(
+        "webhook_opnsense_rate_limit_wait_seconds",
+        "Time OPNsense API calls waited for the client side rate limit"
+    )
+    .unwrap()
+});
+
+pub static REPLICATION_FAILURES: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "webhook_replication_failures_total",
+        "Change batches that could not be fully replicated, by replica",
+        &["instance"]
+    )
+    .unwrap()
+});
+
+// render encodes all registered metrics in the Prometheus
+// text exposition format.
+pub fn render() -> anyhow::Result<String> {
+    Ok(prometheus::TextEncoder::new().encode_to_string(&prometheus::gather())?)
+}
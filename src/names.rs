@@ -0,0 +1,154 @@
+use crate::config::NameRewriteConfig;
+use crate::external_dns::Endpoint;
+
+// Name rewrite rules map the names external-dns publishes to the
+// names host overrides are created under, e.g. *.k8s.example.com
+// to *.example.home. Names are rewritten back when listed, so the
+// mapping is transparent to external-dns.
+
+// forward rewrites a name asked for by external-dns.
+pub fn forward(name: &str, rules: &[NameRewriteConfig]) -> String {
+    rules
+        .iter()
+        .find_map(|rule| match rule {
+            NameRewriteConfig::Suffix { from, to } => replace_suffix(name, from, to),
+            NameRewriteConfig::Regex { from, to, .. } => from
+                .is_match(name)
+                .then(|| from.replace(name, to).into_owned()),
+        })
+        .unwrap_or_else(|| name.to_owned())
+}
+
+// reverse undoes forward for a name read from OPNsense.
+pub fn reverse(name: &str, rules: &[NameRewriteConfig]) -> String {
+    rules
+        .iter()
+        .find_map(|rule| match rule {
+            NameRewriteConfig::Suffix { from, to } => replace_suffix(name, to, from),
+            NameRewriteConfig::Regex {
+                reverse_from,
+                reverse_to,
+                ..
+            } => reverse_from
+                .is_match(name)
+                .then(|| reverse_from.replace(name, reverse_to).into_owned()),
+        })
+        .unwrap_or_else(|| name.to_owned())
+}
+
+// forward_endpoint rewrites the name of an endpoint.
+pub fn forward_endpoint(endpoint: Endpoint, rules: &[NameRewriteConfig]) -> Endpoint {
+    Endpoint {
+        dns_name: forward(&endpoint.dns_name, rules),
+        ..endpoint
+    }
+}
+
+// external_domains lists the domains external-dns publishes
+// names in which are rewritten into the managed zones, so they
+// pass the domain filter it negotiates with the webhook.
+pub fn external_domains(zones: &[String], rules: &[NameRewriteConfig]) -> Vec<String> {
+    rules
+        .iter()
+        .filter_map(|rule| match rule {
+            NameRewriteConfig::Suffix { from, to } => {
+                let to = to.trim_matches('.');
+                zones
+                    .iter()
+                    .any(|z| to == z || to.ends_with(&format!(".{z}")))
+                    .then(|| from.trim_matches('.').to_owned())
+            }
+            NameRewriteConfig::Regex { domain, .. } => domain.clone(),
+        })
+        .collect()
+}
+
+fn replace_suffix(name: &str, from: &str, to: &str) -> Option<String> {
+    let from = from.trim_matches('.');
+    let to = to.trim_matches('.');
+
+    if name == from {
+        return Some(to.to_owned());
+    }
+
+    name.strip_suffix(from)
+        .and_then(|prefix| prefix.strip_suffix('.'))
+        .map(|prefix| format!("{prefix}.{to}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+
+    fn suffix(from: &str, to: &str) -> NameRewriteConfig {
+        NameRewriteConfig::Suffix {
+            from: from.to_owned(),
+            to: to.to_owned(),
+        }
+    }
+
+    fn regex() -> NameRewriteConfig {
+        NameRewriteConfig::Regex {
+            from: Regex::new(r"^(.+)\.svc\.example\.com$").unwrap(),
+            to: "$1.svc.home.arpa".to_owned(),
+            reverse_from: Regex::new(r"^(.+)\.svc\.home\.arpa$").unwrap(),
+            reverse_to: "$1.svc.example.com".to_owned(),
+            domain: Some("svc.example.com".to_owned()),
+        }
+    }
+
+    #[test]
+    fn suffix_round_trip() {
+        let rules = [suffix(".k8s.example.com.", "example.home")];
+
+        assert_eq!(forward("www.k8s.example.com", &rules), "www.example.home");
+        assert_eq!(reverse("www.example.home", &rules), "www.k8s.example.com");
+        assert_eq!(forward("k8s.example.com", &rules), "example.home");
+        assert_eq!(reverse("example.home", &rules), "k8s.example.com");
+    }
+
+    #[test]
+    fn suffix_matches_whole_labels() {
+        let rules = [suffix("example.com", "example.home")];
+
+        assert_eq!(forward("www.myexample.com", &rules), "www.myexample.com");
+        assert_eq!(reverse("www.myexample.home", &rules), "www.myexample.home");
+    }
+
+    #[test]
+    fn regex_round_trip() {
+        let rules = [regex()];
+
+        assert_eq!(forward("api.svc.example.com", &rules), "api.svc.home.arpa");
+        assert_eq!(reverse("api.svc.home.arpa", &rules), "api.svc.example.com");
+        assert_eq!(forward("www.example.com", &rules), "www.example.com");
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let rules = [
+            suffix("lab.example.com", "lab.home.arpa"),
+            suffix("example.com", "home.arpa"),
+        ];
+
+        assert_eq!(forward("a.lab.example.com", &rules), "a.lab.home.arpa");
+        assert_eq!(forward("a.example.com", &rules), "a.home.arpa");
+        assert_eq!(reverse("a.lab.home.arpa", &rules), "a.lab.example.com");
+    }
+
+    #[test]
+    fn external_domains_of_managed_zones() {
+        let zones = ["home.arpa".to_owned()];
+        let rules = [
+            suffix(".k8s.example.com", "k8s.home.arpa."),
+            suffix("other.example.com", "other.arpa"),
+            regex(),
+        ];
+
+        assert_eq!(
+            external_domains(&zones, &rules),
+            ["k8s.example.com", "svc.example.com"]
+        );
+    }
+}
@@ -0,0 +1,195 @@
+use crate::opnsense::dnsmasq::{Dnsmasq, HostRecord};
+use crate::opnsense::unbound::{HostOverrideRecord, Unbound, Zone};
+use crate::opnsense::Result;
+
+// Backend abstracts the OPNsense DNS service records are written
+// to. Records are exchanged as Unbound host overrides whatever
+// the backend, so the rest of the webhook needs not care.
+pub trait Backend {
+    fn name(&self) -> &'static str;
+    async fn list_zones(&self) -> Result<Vec<Zone>>;
+    // search_records lists the records with any field containing
+    // phrase.
+    async fn search_records(&self, phrase: &str) -> Result<Vec<HostOverrideRecord>>;
+    async fn list_records(&self) -> Result<Vec<HostOverrideRecord>> {
+        self.search_records("").await
+    }
+    // add_record creates the record and returns its uuid.
+    async fn add_record(&self, record: &HostOverrideRecord) -> Result<String>;
+    async fn set_record(&self, uuid: &str, record: &HostOverrideRecord) -> Result<()>;
+    async fn delete_record(&self, uuid: &str) -> Result<()>;
+    // apply makes the service serve the records changed so far.
+    async fn apply(&self) -> Result<()>;
+    async fn status(&self) -> Result<String>;
+}
+
+// DnsBackend is the backend selected in the configuration.
+pub enum DnsBackend {
+    Unbound(Unbound),
+    Dnsmasq(Dnsmasq),
+}
+
+impl Backend for DnsBackend {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Unbound(b) => b.name(),
+            Self::Dnsmasq(b) => b.name(),
+        }
+    }
+    async fn list_zones(&self) -> Result<Vec<Zone>> {
+        match self {
+            Self::Unbound(b) => b.list_zones().await,
+            Self::Dnsmasq(b) => b.list_zones().await,
+        }
+    }
+    async fn search_records(&self, phrase: &str) -> Result<Vec<HostOverrideRecord>> {
+        match self {
+            Self::Unbound(b) => b.search_records(phrase).await,
+            Self::Dnsmasq(b) => b.search_records(phrase).await,
+        }
+    }
+    async fn add_record(&self, record: &HostOverrideRecord) -> Result<String> {
+        match self {
+            Self::Unbound(b) => b.add_record(record).await,
+            Self::Dnsmasq(b) => b.add_record(record).await,
+        }
+    }
+    async fn set_record(&self, uuid: &str, record: &HostOverrideRecord) -> Result<()> {
+        match self {
+            Self::Unbound(b) => b.set_record(uuid, record).await,
+            Self::Dnsmasq(b) => b.set_record(uuid, record).await,
+        }
+    }
+    async fn delete_record(&self, uuid: &str) -> Result<()> {
+        match self {
+            Self::Unbound(b) => b.delete_record(uuid).await,
+            Self::Dnsmasq(b) => b.delete_record(uuid).await,
+        }
+    }
+    async fn apply(&self) -> Result<()> {
+        match self {
+            Self::Unbound(b) => b.apply().await,
+            Self::Dnsmasq(b) => b.apply().await,
+        }
+    }
+    async fn status(&self) -> Result<String> {
+        match self {
+            Self::Unbound(b) => b.status().await,
+            Self::Dnsmasq(b) => b.status().await,
+        }
+    }
+}
+
+impl Backend for Unbound {
+    fn name(&self) -> &'static str {
+        "unbound"
+    }
+    async fn list_zones(&self) -> Result<Vec<Zone>> {
+        Ok(self.diagnostics().list_local_zones().await?.data)
+    }
+    async fn search_records(&self, phrase: &str) -> Result<Vec<HostOverrideRecord>> {
+        Ok(self
+            .settings()
+            .search_host_override_by(phrase)
+            .await?
+            .rows
+            .into_iter()
+            .collect())
+    }
+    async fn add_record(&self, record: &HostOverrideRecord) -> Result<String> {
+        Ok(self.settings().add_host_override(record).await?.uuid)
+    }
+    async fn set_record(&self, uuid: &str, record: &HostOverrideRecord) -> Result<()> {
+        let res = self.settings().set_host_override(uuid, record).await?;
+        tracing::debug!(result = res.result, "updated host override");
+        expect_result(&res.result, &res.validations, &["saved"])
+    }
+    async fn delete_record(&self, uuid: &str) -> Result<()> {
+        let res = self.settings().delete_host_override(uuid).await?;
+        tracing::debug!(result = res.result, "deleted host override");
+        expect_result(&res.result, &res.validations, DELETED)
+    }
+    async fn apply(&self) -> Result<()> {
+        let res = self.service().restart().await?;
+        tracing::debug!(response = ?res.response, "restarted unbound");
+        Ok(())
+    }
+    async fn status(&self) -> Result<String> {
+        Ok(self.service().status().await?.status)
+    }
+}
+
+impl Backend for Dnsmasq {
+    fn name(&self) -> &'static str {
+        "dnsmasq"
+    }
+    // Dnsmasq answers for host entries of any domain and forwards
+    // everything else, as a transparent Unbound zone would. There
+    // is nothing to discover, the zones are the domain filters.
+    async fn list_zones(&self) -> Result<Vec<Zone>> {
+        Ok(self
+            .zones()
+            .iter()
+            .map(|z| Zone {
+                zone: format!("{z}."),
+                r#type: "transparent".to_owned(),
+            })
+            .collect())
+    }
+    async fn search_records(&self, phrase: &str) -> Result<Vec<HostOverrideRecord>> {
+        Ok(self
+            .settings()
+            .search_host(phrase)
+            .await?
+            .rows
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+    async fn add_record(&self, record: &HostOverrideRecord) -> Result<String> {
+        Ok(self
+            .settings()
+            .add_host(&HostRecord::try_from(record)?)
+            .await?
+            .uuid)
+    }
+    async fn set_record(&self, uuid: &str, record: &HostOverrideRecord) -> Result<()> {
+        let res = self
+            .settings()
+            .set_host(uuid, &HostRecord::try_from(record)?)
+            .await?;
+        tracing::debug!(result = res.result, "updated host entry");
+        expect_result(&res.result, &res.validations, &["saved"])
+    }
+    async fn delete_record(&self, uuid: &str) -> Result<()> {
+        let res = self.settings().delete_host(uuid).await?;
+        tracing::debug!(result = res.result, "deleted host entry");
+        expect_result(&res.result, &res.validations, DELETED)
+    }
+    async fn apply(&self) -> Result<()> {
+        let res = self.service().reconfigure().await?;
+        tracing::debug!(status = res.status, "reconfigured dnsmasq");
+        Ok(())
+    }
+    async fn status(&self) -> Result<String> {
+        Ok(self.service().status().await?.status)
+    }
+}
+
+// A host override already gone counts as deleted.
+const DELETED: &[&str] = &["deleted", "not found"];
+
+// expect_result fails unless OPNsense reports an expected result.
+// Rejected writes are answered with a successful HTTP status and
+// e.g. {"result": "failed", "validations": {...}}.
+fn expect_result(
+    result: &str,
+    validations: &Option<serde_json::Value>,
+    expected: &[&str],
+) -> Result<()> {
+    match (expected.contains(&result), validations) {
+        (true, _) => Ok(()),
+        (false, Some(v)) => anyhow::bail!("opnsense reported {result:?}: {v}"),
+        (false, None) => anyhow::bail!("opnsense reported {result:?}"),
+    }
+}
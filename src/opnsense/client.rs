@@ -2,6 +2,7 @@ use serde::de::DeserializeOwned;
 use serde_json::Value;
 
 use crate::config::Config;
+use crate::opnsense::ratelimit::RateLimiter;
 use crate::opnsense::Result;
 
 #[derive(Clone)]
@@ -9,6 +10,7 @@ pub struct Client {
     auth: ClientAuth,
     client: reqwest::Client,
     base_url: reqwest::Url,
+    limiter: Option<RateLimiter>,
 }
 
 #[derive(Clone)]
@@ -17,29 +19,28 @@ struct ClientAuth {
     secret: String,
 }
 
-impl TryFrom<&Config> for Client {
-    type Error = anyhow::Error;
-
-    fn try_from(config: &Config) -> std::result::Result<Self, Self::Error> {
+impl Client {
+    // try_new builds a client from the configuration. The rate
+    // limiter is passed in so it outlives clients rebuilt when
+    // credentials are rotated.
+    pub fn try_new(config: &Config, limiter: Option<RateLimiter>) -> Result<Self> {
         let mut builder =
             reqwest::Client::builder().danger_accept_invalid_certs(config.allow_invalid_certs);
 
-        for c in config.certificate_bundle.iter() {
-            builder = builder.add_root_certificate(c.clone());
+        for c in config.read_certificate_bundle()? {
+            builder = builder.add_root_certificate(c);
         }
 
         Ok(Self {
             auth: ClientAuth {
-                key: config.key.clone(),
-                secret: config.secret.clone(),
+                key: config.read_key()?,
+                secret: config.read_secret()?,
             },
             client: builder.build()?,
             base_url: config.base.join("api/")?,
+            limiter,
         })
     }
-}
-
-impl Client {
     pub fn with_path(&self, path: &str) -> Result<Self> {
         Ok(Self {
             base_url: self.base_url.join(path)?,
@@ -47,6 +48,8 @@ impl Client {
         })
     }
     pub async fn get<M: Method>(&self, path: &str) -> Result<M::Response> {
+        self.throttle().await;
+
         Ok(self
             .client
             .get(self.base_url.join(path).unwrap())
@@ -58,6 +61,8 @@ impl Client {
             .await?)
     }
     pub async fn post<M: Method>(&self, path: &str, json: Value) -> Result<M::Response> {
+        self.throttle().await;
+
         Ok(self
             .client
             .post(self.base_url.join(path).unwrap())
@@ -69,6 +74,11 @@ impl Client {
             .json::<M::Response>()
             .await?)
     }
+    async fn throttle(&self) {
+        if let Some(limiter) = &self.limiter {
+            limiter.acquire().await;
+        }
+    }
 }
 
 pub trait Method {
@@ -0,0 +1,229 @@
+use crate::opnsense::client::Method;
+use crate::opnsense::unbound::HostOverrideRecord;
+use crate::opnsense::{Client, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::net::IpAddr;
+
+pub struct Dnsmasq {
+    client: Client,
+    zones: Vec<String>,
+}
+
+impl Dnsmasq {
+    pub fn new(client: Client, zones: Vec<String>) -> Self {
+        Self {
+            client: client.with_path("dnsmasq/").unwrap(),
+            zones,
+        }
+    }
+    pub fn zones(&self) -> &[String] {
+        &self.zones
+    }
+    pub fn settings(&self) -> Settings {
+        Settings {
+            client: self.client.with_path("settings/").unwrap(),
+        }
+    }
+    pub fn service(&self) -> Service {
+        Service {
+            client: self.client.with_path("service/").unwrap(),
+        }
+    }
+}
+
+pub struct Settings {
+    client: Client,
+}
+
+impl Settings {
+    // search_host lists the host entries with any field
+    // containing phrase.
+    pub async fn search_host(&self, phrase: &str) -> Result<SettingsListResponse> {
+        let res = self
+            .client
+            .post::<SettingsMethod>(
+                "searchHost/",
+                json!({
+                    "current": 1,
+                    "rowCount": -1,
+                    "searchPhrase": phrase,
+                    "sort": {}
+                }),
+            )
+            .await?;
+
+        match res {
+            SettingsResponse::List(res) => Ok(res),
+            _ => Err(anyhow::anyhow!("invalid response format")),
+        }
+    }
+    pub async fn delete_host(&self, uuid: &str) -> Result<SettingsUpdateResponse> {
+        let res = self
+            .client
+            .post::<SettingsMethod>(&format!("delHost/{uuid}"), Value::Null)
+            .await?;
+
+        match res {
+            SettingsResponse::Update(res) => Ok(res),
+            _ => Err(anyhow::anyhow!("invalid response format")),
+        }
+    }
+    pub async fn add_host(&self, host: &HostRecord) -> Result<SettingsAddResponse> {
+        let res = self
+            .client
+            .post::<SettingsMethod>(
+                "addHost/",
+                json!({
+                    "host": host
+                }),
+            )
+            .await?;
+
+        match res {
+            SettingsResponse::Add(res) => Ok(res),
+            _ => Err(anyhow::anyhow!("invalid response format")),
+        }
+    }
+    pub async fn set_host(&self, uuid: &str, host: &HostRecord) -> Result<SettingsUpdateResponse> {
+        let res = self
+            .client
+            .post::<SettingsMethod>(
+                &format!("setHost/{uuid}"),
+                json!({
+                    "host": host
+                }),
+            )
+            .await?;
+
+        match res {
+            SettingsResponse::Update(res) => Ok(res),
+            _ => Err(anyhow::anyhow!("invalid response format")),
+        }
+    }
+}
+
+struct SettingsMethod;
+
+impl Method for SettingsMethod {
+    type Response = SettingsResponse;
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum SettingsResponse {
+    Add(SettingsAddResponse),
+    List(SettingsListResponse),
+    Update(SettingsUpdateResponse),
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SettingsAddResponse {
+    pub uuid: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SettingsListResponse {
+    pub rows: Vec<HostRecord>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SettingsUpdateResponse {
+    pub result: String,
+    #[serde(default)]
+    pub validations: Option<serde_json::Value>,
+}
+
+// HostRecord is a Dnsmasq host entry. Unlike Unbound host
+// overrides, it carries all addresses of a host in ip and
+// cannot be disabled.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct HostRecord {
+    #[serde(skip_serializing, default)]
+    pub uuid: String,
+    pub host: String,
+    pub domain: String,
+    pub ip: String,
+    #[serde(default)]
+    pub descr: String,
+}
+
+// Host entries with a single address map to a host override of
+// the matching type. Others get an empty record type, which the
+// webhook skips as unsupported.
+impl From<HostRecord> for HostOverrideRecord {
+    fn from(value: HostRecord) -> Self {
+        let rr = match value.ip.trim().parse::<IpAddr>() {
+            Ok(IpAddr::V4(_)) => "A",
+            Ok(IpAddr::V6(_)) => "AAAA",
+            Err(_) => "",
+        };
+
+        Self {
+            uuid: value.uuid,
+            enabled: "1".to_owned(),
+            domain: value.domain,
+            rr: rr.to_owned(),
+            server: value.ip.trim().to_owned(),
+            hostname: value.host,
+            mx: String::new(),
+            mxprio: String::new(),
+            description: value.descr,
+        }
+    }
+}
+
+impl TryFrom<&HostOverrideRecord> for HostRecord {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &HostOverrideRecord) -> std::result::Result<Self, Self::Error> {
+        if value.enabled.trim() != "1" {
+            anyhow::bail!("dnsmasq host entries cannot be disabled");
+        }
+
+        Ok(Self {
+            uuid: value.uuid.clone(),
+            host: value.hostname.clone(),
+            domain: value.domain.clone(),
+            ip: value.server.clone(),
+            descr: value.description.clone(),
+        })
+    }
+}
+
+pub struct Service {
+    client: Client,
+}
+
+impl Service {
+    pub async fn reconfigure(&self) -> Result<ServiceReconfigureResponse> {
+        self.client
+            .post::<ServiceReconfigureMethod>("reconfigure/", Value::Null)
+            .await
+    }
+    pub async fn status(&self) -> Result<ServiceStatusResponse> {
+        self.client.get::<ServiceStatusMethod>("status/").await
+    }
+}
+
+struct ServiceReconfigureMethod;
+
+impl Method for ServiceReconfigureMethod {
+    type Response = ServiceReconfigureResponse;
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ServiceReconfigureResponse {
+    pub status: String,
+}
+
+struct ServiceStatusMethod;
+
+impl Method for ServiceStatusMethod {
+    type Response = ServiceStatusResponse;
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ServiceStatusResponse {
+    pub status: String,
+}
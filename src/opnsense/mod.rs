@@ -1,20 +1,85 @@
-use crate::config::Config;
+use crate::config::{BackendKind, Config};
+use crate::watch;
+mod backend;
 mod client;
+pub mod dnsmasq;
+mod ratelimit;
 pub mod unbound;
 
+pub use backend::{Backend, DnsBackend};
 use client::Client;
+use dnsmasq::Dnsmasq;
+use ratelimit::RateLimiter;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
 use unbound::Unbound;
 
 type Result<T> = anyhow::Result<T>;
 
 #[derive(Clone)]
 pub struct Opnsense {
-    client: Client,
+    client: Arc<RwLock<Client>>,
+    limiter: Option<RateLimiter>,
+    kind: BackendKind,
+    domain_filters: Vec<String>,
 }
 
 impl Opnsense {
-    pub fn unbound(&self) -> Unbound {
-        Unbound::new(self.client.clone())
+    // backend returns the DNS service records are managed in.
+    pub fn backend(&self) -> DnsBackend {
+        match self.kind {
+            BackendKind::Unbound => DnsBackend::Unbound(Unbound::new(self.client())),
+            BackendKind::Dnsmasq => DnsBackend::Dnsmasq(Dnsmasq::new(
+                self.client(),
+                self.domain_filters
+                    .iter()
+                    .map(|f| f.strip_prefix('.').unwrap_or(f).to_owned())
+                    .collect(),
+            )),
+        }
+    }
+
+    // with_domain_filters returns an Opnsense sharing this one's
+    // client and rate limiter, managing the given domains.
+    pub fn with_domain_filters(&self, domain_filters: &[String]) -> Self {
+        Self {
+            domain_filters: domain_filters.to_vec(),
+            ..self.clone()
+        }
+    }
+
+    fn client(&self) -> Client {
+        self.client
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    // reload_on_change rebuilds the client whenever the key, secret
+    // or certificate bundle files change, so credentials can be
+    // rotated without restarting. Requests already in flight keep
+    // using the client they started with.
+    pub fn reload_on_change(&self, config: &Config) {
+        let files = config.secret_files();
+        if files.is_empty() {
+            return;
+        }
+
+        let client = self.client.clone();
+        let limiter = self.limiter.clone();
+        let config = config.clone();
+
+        watch::spawn(
+            files,
+            Duration::from_secs(config.watch_interval),
+            move || match Client::try_new(&config, limiter.clone()) {
+                Ok(c) => {
+                    *client.write().unwrap_or_else(|e| e.into_inner()) = c;
+                    tracing::info!("reloaded opnsense credentials");
+                }
+                Err(e) => tracing::error!("could not reload opnsense credentials: {e:#}"),
+            },
+        );
     }
 }
 
@@ -22,8 +87,17 @@ impl TryFrom<&Config> for Opnsense {
     type Error = anyhow::Error;
 
     fn try_from(config: &Config) -> std::result::Result<Self, Self::Error> {
+        let limiter = config
+            .rate_limit
+            .as_ref()
+            .map(RateLimiter::new)
+            .transpose()?;
+
         Ok(Self {
-            client: Client::try_from(config)?,
+            client: Arc::new(RwLock::new(Client::try_new(config, limiter.clone())?)),
+            limiter,
+            kind: config.backend,
+            domain_filters: config.domain_filters.clone(),
         })
     }
 }
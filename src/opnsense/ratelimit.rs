@@ -0,0 +1,147 @@
+use crate::config::RateLimitConfig;
+use crate::metrics::RATE_LIMIT_WAIT_SECONDS;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
+
+// RateLimiter is a token bucket shared by every clone, so all the
+// clients derived from one Opnsense draw from the same budget.
+#[derive(Clone)]
+pub struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig) -> anyhow::Result<Self> {
+        if !config.requests_per_second.is_finite() || config.requests_per_second <= 0.0 {
+            anyhow::bail!("rate_limit.requests_per_second must be positive");
+        }
+
+        let burst = f64::from(config.burst.max(1));
+
+        Ok(Self {
+            rate: config.requests_per_second,
+            burst,
+            bucket: Arc::new(Mutex::new(Bucket {
+                tokens: burst,
+                updated_at: Instant::now(),
+            })),
+        })
+    }
+
+    // acquire takes a token, waiting for one to become available.
+    // Tokens are reserved before sleeping, so waiting callers are
+    // served in the order they arrived.
+    pub async fn acquire(&self) {
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap_or_else(|e| e.into_inner());
+            let now = Instant::now();
+
+            let refill = now.duration_since(bucket.updated_at).as_secs_f64() * self.rate;
+            bucket.tokens = (bucket.tokens + refill).min(self.burst) - 1.0;
+            bucket.updated_at = now;
+
+            match bucket.tokens {
+                t if t >= 0.0 => Duration::ZERO,
+                t => Duration::from_secs_f64(-t / self.rate),
+            }
+        };
+
+        RATE_LIMIT_WAIT_SECONDS.observe(wait.as_secs_f64());
+
+        if !wait.is_zero() {
+            tracing::trace!(?wait, "waiting for opnsense rate limit");
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(requests_per_second: f64, burst: u32) -> RateLimiter {
+        RateLimiter::new(&RateLimitConfig {
+            requests_per_second,
+            burst,
+        })
+        .unwrap()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn burst_then_rate() {
+        let limiter = limiter(10.0, 2);
+        let start = Instant::now();
+
+        limiter.acquire().await;
+        limiter.acquire().await;
+        assert_eq!(start.elapsed(), Duration::ZERO);
+
+        limiter.acquire().await;
+        limiter.acquire().await;
+        assert_eq!(start.elapsed(), Duration::from_millis(200));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn bucket_refills_while_idle() {
+        let limiter = limiter(10.0, 2);
+
+        limiter.acquire().await;
+        limiter.acquire().await;
+        tokio::time::sleep(Duration::from_secs(10)).await;
+
+        let start = Instant::now();
+        limiter.acquire().await;
+        limiter.acquire().await;
+        assert_eq!(start.elapsed(), Duration::ZERO);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn clones_share_the_bucket() {
+        let limiter = limiter(10.0, 1);
+        let clone = limiter.clone();
+        let start = Instant::now();
+
+        let waits = futures::future::join_all((0..3).map(|i| {
+            let limiter = match i % 2 {
+                0 => limiter.clone(),
+                _ => clone.clone(),
+            };
+            async move {
+                limiter.acquire().await;
+                start.elapsed()
+            }
+        }))
+        .await;
+
+        assert_eq!(
+            waits,
+            [
+                Duration::ZERO,
+                Duration::from_millis(100),
+                Duration::from_millis(200)
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_rates() {
+        assert!(RateLimiter::new(&RateLimitConfig {
+            requests_per_second: 0.0,
+            burst: 1
+        })
+        .is_err());
+        assert!(RateLimiter::new(&RateLimitConfig {
+            requests_per_second: f64::NAN,
+            burst: 1
+        })
+        .is_err());
+    }
+}
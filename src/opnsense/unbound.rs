@@ -35,9 +35,17 @@ pub struct Diagnostics {
 
 impl Diagnostics {
     pub async fn list_local_zones(&self) -> Result<ListLocalZonesResponse> {
-        self.client
+        let res = self
+            .client
             .get::<ListLocalZonesMethod>("listlocalzones/")
-            .await
+            .await?;
+
+        match res.status.as_str() {
+            "ok" => Ok(res),
+            s => Err(anyhow::anyhow!(
+                "unexpected status listing local zones: {s}"
+            )),
+        }
     }
 }
 
@@ -73,7 +81,9 @@ pub struct Settings {
 }
 
 impl Settings {
-    pub async fn search_host_override(&self) -> Result<SettingsListResponse> {
+    // search_host_override_by lists the host overrides with any
+    // field containing phrase.
+    pub async fn search_host_override_by(&self, phrase: &str) -> Result<SettingsListResponse> {
         let res = self
             .client
             .post::<SettingsMethod>(
@@ -81,7 +91,7 @@ impl Settings {
                 json!({
                     "current": 1,
                     "rowCount": -1,
-                    "searchPhrase": "",
+                    "searchPhrase": phrase,
                     "sort": {}
                 }),
             )
@@ -197,6 +207,8 @@ pub struct HostOverrideRecord {
 #[derive(Deserialize, Debug)]
 pub struct SettingsUpdateResponse {
     pub result: String,
+    #[serde(default)]
+    pub validations: Option<serde_json::Value>,
 }
 
 pub struct Service {
@@ -214,6 +226,20 @@ impl Service {
             ServiceResponse::Restart(res) => Ok(res),
         }
     }
+    pub async fn status(&self) -> Result<ServiceStatusResponse> {
+        self.client.get::<ServiceStatusMethod>("status/").await
+    }
+}
+
+struct ServiceStatusMethod;
+
+impl Method for ServiceStatusMethod {
+    type Response = ServiceStatusResponse;
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ServiceStatusResponse {
+    pub status: String,
 }
 
 struct ServiceMethod;
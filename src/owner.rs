@@ -0,0 +1,37 @@
+// Host overrides written by the webhook carry a marker in their
+// description naming the owner, in the same format external-dns
+// uses for its TXT registry. Only host overrides bearing the
+// marker of the configured owner are garbage collected.
+
+fn marker(owner: &str) -> String {
+    format!("[heritage=external-dns,external-dns/owner={owner}]")
+}
+
+// tag appends the owner marker to a description.
+pub fn tag(description: &str, owner: Option<&str>) -> String {
+    let description = strip(description);
+
+    match owner {
+        None => description.to_owned(),
+        Some(owner) if description.is_empty() => marker(owner),
+        Some(owner) => format!("{description} {}", marker(owner)),
+    }
+}
+
+// strip removes any owner marker from a description.
+pub fn strip(description: &str) -> &str {
+    match description.find("[heritage=external-dns,") {
+        Some(i) if description.ends_with(']') => description[..i].trim_end(),
+        _ => description,
+    }
+}
+
+// is_owned_by tells if a host override belongs to owner. Without
+// an owner every host override in the managed zones is considered
+// owned, as it is reported to external-dns.
+pub fn is_owned_by(description: &str, owner: Option<&str>) -> bool {
+    match owner {
+        None => true,
+        Some(owner) => description.ends_with(&marker(owner)),
+    }
+}
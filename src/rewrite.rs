@@ -0,0 +1,203 @@
+use crate::config::RewriteConfig;
+use crate::opnsense::unbound::HostOverrideRecord;
+use ipnet::IpNet;
+use std::net::IpAddr;
+
+// Rewrite rules map the targets external-dns asks for to other
+// addresses, e.g. public load balancer addresses to their NAT
+// address, for split-horizon DNS. Records are rewritten on their
+// way to OPNsense and back when listed, so external-dns keeps
+// seeing its own targets.
+
+// validate checks that every rule maps networks of the same
+// family and size.
+pub fn validate(rules: &[RewriteConfig]) -> anyhow::Result<()> {
+    for rule in rules {
+        let same_family = matches!(
+            (rule.from, rule.to),
+            (IpNet::V4(_), IpNet::V4(_)) | (IpNet::V6(_), IpNet::V6(_))
+        );
+
+        if !same_family || rule.from.prefix_len() != rule.to.prefix_len() {
+            anyhow::bail!(
+                "cannot rewrite {} to {}: networks must be of the same family and size",
+                rule.from,
+                rule.to
+            );
+        }
+    }
+
+    Ok(())
+}
+
+// forward rewrites the target of a record written to OPNsense.
+pub fn forward(record: HostOverrideRecord, rules: &[RewriteConfig]) -> HostOverrideRecord {
+    apply(record, rules, |rule| (rule.from, rule.to))
+}
+
+// reverse undoes forward for a record read from OPNsense.
+pub fn reverse(record: HostOverrideRecord, rules: &[RewriteConfig]) -> HostOverrideRecord {
+    apply(record, rules, |rule| (rule.to, rule.from))
+}
+
+fn apply(
+    mut record: HostOverrideRecord,
+    rules: &[RewriteConfig],
+    direction: impl Fn(&RewriteConfig) -> (IpNet, IpNet),
+) -> HostOverrideRecord {
+    let Ok(ip) = record.server.parse::<IpAddr>() else {
+        return record;
+    };
+
+    let rewritten = rules
+        .iter()
+        .filter(|rule| rule.zones.is_empty() || rule.zones.contains(&record.domain))
+        .find_map(|rule| {
+            let (from, to) = direction(rule);
+            translate(ip, from, to)
+        });
+
+    if let Some(target) = rewritten {
+        tracing::debug!(
+            fqdn = format!("{}.{}", record.hostname, record.domain),
+            from = record.server,
+            %target,
+            "rewrote target"
+        );
+        record.server = target.to_string();
+    }
+
+    record
+}
+
+// translate moves ip from one network to the same offset within
+// the other.
+fn translate(ip: IpAddr, from: IpNet, to: IpNet) -> Option<IpAddr> {
+    if !from.contains(&ip) {
+        return None;
+    }
+
+    match (ip, from, to) {
+        (IpAddr::V4(ip), IpNet::V4(from), IpNet::V4(to)) => Some(IpAddr::V4(
+            (u32::from(to.network()) | (u32::from(ip) & u32::from(from.hostmask()))).into(),
+        )),
+        (IpAddr::V6(ip), IpNet::V6(from), IpNet::V6(to)) => Some(IpAddr::V6(
+            (u128::from(to.network()) | (u128::from(ip) & u128::from(from.hostmask()))).into(),
+        )),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(from: &str, to: &str, zones: &[&str]) -> RewriteConfig {
+        RewriteConfig {
+            from: from.parse().unwrap(),
+            to: to.parse().unwrap(),
+            zones: zones.iter().map(|z| z.to_string()).collect(),
+        }
+    }
+
+    fn record(domain: &str, server: &str) -> HostOverrideRecord {
+        HostOverrideRecord {
+            uuid: String::new(),
+            enabled: "1".to_owned(),
+            domain: domain.to_owned(),
+            rr: "A".to_owned(),
+            server: server.to_owned(),
+            hostname: "www".to_owned(),
+            mx: String::new(),
+            mxprio: String::new(),
+            description: String::new(),
+        }
+    }
+
+    #[test]
+    fn translate_keeps_the_offset() {
+        let from = "203.0.113.0/24".parse().unwrap();
+        let to = "10.0.1.0/24".parse().unwrap();
+
+        assert_eq!(
+            translate("203.0.113.42".parse().unwrap(), from, to),
+            Some("10.0.1.42".parse().unwrap())
+        );
+        assert_eq!(
+            translate("10.0.1.42".parse().unwrap(), to, from),
+            Some("203.0.113.42".parse().unwrap())
+        );
+        assert_eq!(translate("198.51.100.1".parse().unwrap(), from, to), None);
+    }
+
+    #[test]
+    fn translate_v6() {
+        let from = "2001:db8:1::/64".parse().unwrap();
+        let to = "fd00:1::/64".parse().unwrap();
+
+        assert_eq!(
+            translate("2001:db8:1::abcd".parse().unwrap(), from, to),
+            Some("fd00:1::abcd".parse().unwrap())
+        );
+        assert_eq!(
+            translate("fd00:1::abcd".parse().unwrap(), to, from),
+            Some("2001:db8:1::abcd".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn forward_and_reverse() {
+        let rules = [rule("203.0.113.0/24", "10.0.1.0/24", &[])];
+
+        let written = forward(record("home.arpa", "203.0.113.7"), &rules);
+        assert_eq!(written.server, "10.0.1.7");
+
+        let listed = reverse(written, &rules);
+        assert_eq!(listed.server, "203.0.113.7");
+    }
+
+    #[test]
+    fn rules_restricted_to_zones() {
+        let rules = [rule("203.0.113.0/24", "10.0.1.0/24", &["lab.arpa"])];
+
+        assert_eq!(
+            forward(record("lab.arpa", "203.0.113.7"), &rules).server,
+            "10.0.1.7"
+        );
+        assert_eq!(
+            forward(record("home.arpa", "203.0.113.7"), &rules).server,
+            "203.0.113.7"
+        );
+    }
+
+    #[test]
+    fn zones_are_trimmed_when_loaded() {
+        let rules: Vec<RewriteConfig> = serde_json::from_str(
+            r#"[{"from": "203.0.113.0/24", "to": "10.0.1.0/24", "zones": ["lab.arpa."]}]"#,
+        )
+        .unwrap();
+
+        assert_eq!(rules[0].zones, ["lab.arpa"]);
+        assert_eq!(
+            forward(record("lab.arpa", "203.0.113.7"), &rules).server,
+            "10.0.1.7"
+        );
+    }
+
+    #[test]
+    fn non_address_targets_are_kept() {
+        let rules = [rule("203.0.113.0/24", "10.0.1.0/24", &[])];
+
+        assert_eq!(
+            forward(record("home.arpa", "www.example.com"), &rules).server,
+            "www.example.com"
+        );
+    }
+
+    #[test]
+    fn validate_rejects_mismatched_networks() {
+        assert!(validate(&[rule("203.0.113.0/24", "10.0.1.0/24", &[])]).is_ok());
+        assert!(validate(&[rule("203.0.113.0/24", "10.0.0.0/16", &[])]).is_err());
+        assert!(validate(&[rule("203.0.113.0/24", "fd00::/120", &[])]).is_err());
+    }
+}
@@ -1,11 +1,18 @@
-use crate::config::Config;
+use crate::config::{Config, ReplicaConfig};
 use crate::external_dns::Endpoint;
+use crate::metrics::{BATCHES_QUEUED, BATCH_WAIT_SECONDS};
 use crate::opnsense::unbound::HostOverrideRecord;
 use crate::opnsense::Opnsense;
+use crate::rewrite;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use std::hash::Hash;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::Instant;
+use tokio::sync::{Mutex, MutexGuard, RwLock};
 
 #[derive(Clone)]
 pub struct AppState<R: RecordCache, Z: ZoneCache> {
@@ -13,6 +20,210 @@ pub struct AppState<R: RecordCache, Z: ZoneCache> {
     pub opnsense: Opnsense,
     pub record_cache: Arc<RwLock<R>>,
     pub zone_cache: Arc<RwLock<Z>>,
+    pub desired: Arc<RwLock<DesiredState>>,
+    pub replicas: Vec<Replica>,
+    pub listing: Arc<Mutex<Option<Listing>>>,
+    listing_generation: Arc<AtomicU64>,
+    batch_lock: Arc<Mutex<()>>,
+}
+
+impl<R: RecordCache, Z: ZoneCache> AppState<R, Z> {
+    // lock_batch serializes everything changing host overrides or
+    // rebuilding the record cache, so change batches, record listings
+    // and background jobs are applied strictly one at a time.
+    pub async fn lock_batch(&self) -> MutexGuard<'_, ()> {
+        let queued = Queued::new();
+        let start = Instant::now();
+
+        let guard = self.batch_lock.lock().await;
+
+        drop(queued);
+        BATCH_WAIT_SECONDS.observe(start.elapsed().as_secs_f64());
+
+        guard
+    }
+
+    // persist_record_cache writes the record cache out if it
+    // changed, once a batch is done and off the cache lock.
+    pub async fn persist_record_cache(&self) -> anyhow::Result<()> {
+        let snapshot = self.record_cache.write().await.take_snapshot()?;
+
+        match snapshot {
+            Some(snapshot) => snapshot.write().await,
+            None => Ok(()),
+        }
+    }
+
+    pub fn listing_generation(&self) -> u64 {
+        self.listing_generation.load(Ordering::SeqCst)
+    }
+
+    // invalidate_listing marks listings fetched so far as stale,
+    // to be called after host overrides have been changed.
+    pub fn invalidate_listing(&self) {
+        self.listing_generation.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+// Queued counts a batch as queued for as long as it lives, so
+// batches whose request is dropped while waiting are uncounted.
+struct Queued;
+
+impl Queued {
+    fn new() -> Self {
+        BATCHES_QUEUED.inc();
+        Self
+    }
+}
+
+impl Drop for Queued {
+    fn drop(&mut self) {
+        BATCHES_QUEUED.dec();
+    }
+}
+
+impl<R: RecordCache> AppState<R, DefaultZoneCache> {
+    pub fn try_new(config: &Config, record_cache: R) -> anyhow::Result<Self> {
+        Self::try_with_firewall(config, &Firewall::try_new(config)?, record_cache)
+    }
+
+    // try_with_firewall builds the state of a tenant reaching the
+    // firewall through connections shared with other tenants.
+    pub fn try_with_firewall(
+        config: &Config,
+        firewall: &Firewall,
+        record_cache: R,
+    ) -> anyhow::Result<Self> {
+        rewrite::validate(&config.rewrites)?;
+
+        Ok(Self {
+            opnsense: firewall
+                .opnsense
+                .with_domain_filters(&config.domain_filters),
+            config: config.clone(),
+            record_cache: Arc::new(RwLock::new(record_cache)),
+            zone_cache: Arc::new(RwLock::new(DefaultZoneCache::new())),
+            desired: Arc::new(RwLock::new(DesiredState::default())),
+            replicas: firewall.replicas.clone(),
+            listing: Arc::new(Mutex::new(None)),
+            listing_generation: Arc::new(AtomicU64::new(0)),
+            batch_lock: firewall.batch_lock.clone(),
+        })
+    }
+}
+
+// Firewall holds what every tenant reaching the same OPNsense
+// instances shares: the clients with their rate limiter, the
+// replicas and the lock applying batches one at a time.
+#[derive(Clone)]
+pub struct Firewall {
+    pub opnsense: Opnsense,
+    pub replicas: Vec<Replica>,
+    batch_lock: Arc<Mutex<()>>,
+}
+
+impl Firewall {
+    pub fn try_new(config: &Config) -> anyhow::Result<Self> {
+        Ok(Self {
+            opnsense: Opnsense::try_from(config)?,
+            replicas: config
+                .replicas
+                .iter()
+                .map(|r| Replica::try_new(config, r))
+                .collect::<anyhow::Result<_>>()?,
+            batch_lock: Arc::new(Mutex::new(())),
+        })
+    }
+
+    // reload_on_change reloads the credentials of the primary
+    // instance and of the replicas when their files change.
+    pub fn reload_on_change(&self, config: &Config) {
+        self.opnsense.reload_on_change(config);
+        for (replica, replica_config) in self.replicas.iter().zip(&config.replicas) {
+            replica
+                .opnsense
+                .reload_on_change(&config.for_replica(replica_config));
+        }
+    }
+}
+
+impl TryFrom<&Config> for AppState<DefaultRecordCache, DefaultZoneCache> {
+    type Error = anyhow::Error;
+
+    fn try_from(config: &Config) -> Result<Self, Self::Error> {
+        Self::try_new(config, DefaultRecordCache::new())
+    }
+}
+
+// Replica is an additional OPNsense instance changes are
+// replicated to. It keeps its own record cache, as UUIDs differ
+// between instances, and fills it through lookups on misses.
+#[derive(Clone)]
+pub struct Replica {
+    pub name: String,
+    pub zones: Vec<String>,
+    pub opnsense: Opnsense,
+    pub record_cache: Arc<RwLock<DefaultRecordCache>>,
+}
+
+impl Replica {
+    pub fn try_new(config: &Config, replica: &ReplicaConfig) -> anyhow::Result<Self> {
+        Ok(Self {
+            name: replica.name.clone(),
+            zones: replica
+                .zones
+                .iter()
+                .map(|z| z.trim_matches('.').to_owned())
+                .collect(),
+            opnsense: Opnsense::try_from(&config.for_replica(replica))
+                .with_context(|| format!("replica {}", replica.name))?,
+            record_cache: Arc::new(RwLock::new(DefaultRecordCache::new())),
+        })
+    }
+
+    // manages tells whether the record belongs to the zones
+    // replicated to this instance.
+    pub fn manages(&self, record: &HostOverrideRecord) -> bool {
+        self.zones.is_empty() || self.zones.contains(&record.domain)
+    }
+}
+
+// Listing is the last list of managed host overrides served on
+// GET /records, shared by requests arriving while it is fetched
+// and, for a configurable time, by the following ones.
+pub struct Listing {
+    pub records: Vec<HostOverrideRecord>,
+    pub fetched_at: Instant,
+    pub generation: u64,
+}
+
+// DesiredState holds the records external-dns last asked for,
+// as seen through adjustendpoints which receives every endpoint
+// of its sources on each synchronisation, and the host overrides
+// last written on its behalf through set_records.
+#[derive(Default)]
+pub struct DesiredState {
+    pub keys: HashSet<Recordkey>,
+    pub seen_at: Option<Instant>,
+    pub applied: HashMap<Recordkey, HostOverrideRecord>,
+}
+
+impl DesiredState {
+    pub fn replace<'a>(&mut self, endpoints: impl IntoIterator<Item = &'a Endpoint>) {
+        self.keys = endpoints
+            .into_iter()
+            .flat_map(|ep| Recordkey::try_from(ep.clone()))
+            .collect();
+        self.seen_at = Some(Instant::now());
+    }
+    pub fn try_record_applied(&mut self, record: &HostOverrideRecord) -> anyhow::Result<()> {
+        self.applied.insert(record.try_into()?, record.clone());
+        Ok(())
+    }
+    pub fn try_forget_applied(&mut self, record: &HostOverrideRecord) -> anyhow::Result<()> {
+        self.applied.remove(&record.try_into()?);
+        Ok(())
+    }
 }
 
 pub trait RecordCache {
@@ -22,7 +233,15 @@ pub trait RecordCache {
         record: &HostOverrideRecord,
     ) -> anyhow::Result<Option<RecordEntry>>;
     fn try_remove_record(&mut self, record: &HostOverrideRecord) -> anyhow::Result<()>;
-    fn clear(&mut self);
+    // try_replace_all builds a fresh cache from records and swaps
+    // it in, leaving the current one untouched on error.
+    fn try_replace_all(&mut self, records: &[HostOverrideRecord]) -> anyhow::Result<()>;
+    // take_snapshot returns what to persist if the cache changed
+    // since the last snapshot. Caches kept in memory only have
+    // nothing to persist.
+    fn take_snapshot(&mut self) -> anyhow::Result<Option<Snapshot>> {
+        Ok(None)
+    }
 }
 
 #[derive(Clone)]
@@ -48,12 +267,131 @@ impl RecordCache for DefaultRecordCache {
         self.0.remove(&record.try_into()?);
         Ok(())
     }
-    fn clear(&mut self) {
-        self.0.clear();
+    fn try_replace_all(&mut self, records: &[HostOverrideRecord]) -> anyhow::Result<()> {
+        self.0 = records
+            .iter()
+            .map(|r| Ok((r.try_into()?, r.try_into()?)))
+            .collect::<anyhow::Result<_>>()?;
+        Ok(())
+    }
+}
+
+// FileRecordCache keeps the records in memory like the default
+// cache and writes them to a JSON file once per batch of changes,
+// so updates and deletes arriving right after a restart still find
+// their UUIDs.
+#[derive(Clone)]
+pub struct FileRecordCache {
+    path: PathBuf,
+    records: DefaultRecordCache,
+    // generation counts changes, written is the generation of
+    // the last snapshot written to disk.
+    generation: u64,
+    written: Arc<Mutex<u64>>,
+}
+
+impl FileRecordCache {
+    pub fn try_open(path: &Path) -> anyhow::Result<Self> {
+        let records = match std::fs::read(path) {
+            Ok(buf) => serde_json::from_slice::<Vec<(Recordkey, RecordEntry)>>(&buf)
+                .with_context(|| format!("could not parse record cache {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => {
+                Err(e).with_context(|| format!("could not read record cache {}", path.display()))?
+            }
+        };
+
+        tracing::info!(records = records.len(), path = %path.display(), "loaded record cache");
+
+        Ok(Self {
+            path: path.to_owned(),
+            records: DefaultRecordCache(records.into_iter().collect()),
+            generation: 0,
+            written: Arc::new(Mutex::new(0)),
+        })
+    }
+}
+
+impl RecordCache for FileRecordCache {
+    fn try_get_record(&self, record: &HostOverrideRecord) -> anyhow::Result<Option<RecordEntry>> {
+        self.records.try_get_record(record)
+    }
+    fn try_insert_record(
+        &mut self,
+        record: &HostOverrideRecord,
+    ) -> anyhow::Result<Option<RecordEntry>> {
+        let previous = self.records.try_insert_record(record)?;
+        self.generation += 1;
+        Ok(previous)
+    }
+    fn try_remove_record(&mut self, record: &HostOverrideRecord) -> anyhow::Result<()> {
+        self.records.try_remove_record(record)?;
+        self.generation += 1;
+        Ok(())
+    }
+    fn try_replace_all(&mut self, records: &[HostOverrideRecord]) -> anyhow::Result<()> {
+        let mut fresh = DefaultRecordCache::new();
+        fresh.try_replace_all(records)?;
+
+        self.records = fresh;
+        self.generation += 1;
+
+        Ok(())
+    }
+    fn take_snapshot(&mut self) -> anyhow::Result<Option<Snapshot>> {
+        // Until a snapshot is written, e.g. after a failed write,
+        // every call takes a new one. While a write is in progress
+        // the snapshot is taken anyway and skipped if outdated.
+        if self
+            .written
+            .try_lock()
+            .is_ok_and(|written| *written == self.generation)
+        {
+            return Ok(None);
+        }
+
+        let buf = serde_json::to_vec(&self.records.0.iter().collect::<Vec<_>>())?;
+
+        Ok(Some(Snapshot {
+            path: self.path.clone(),
+            buf,
+            generation: self.generation,
+            written: self.written.clone(),
+        }))
+    }
+}
+
+// Snapshot is the content of a file backed record cache at some
+// point, written out once the cache lock is released. A snapshot
+// written late never overwrites a newer one.
+pub struct Snapshot {
+    path: PathBuf,
+    buf: Vec<u8>,
+    generation: u64,
+    written: Arc<Mutex<u64>>,
+}
+
+impl Snapshot {
+    pub async fn write(self) -> anyhow::Result<()> {
+        let mut written = self.written.lock().await;
+        if *written >= self.generation {
+            return Ok(());
+        }
+
+        let tmp = self.path.with_extension("tmp");
+        tokio::fs::write(&tmp, &self.buf)
+            .await
+            .with_context(|| format!("could not write record cache {}", tmp.display()))?;
+        tokio::fs::rename(&tmp, &self.path)
+            .await
+            .with_context(|| format!("could not write record cache {}", self.path.display()))?;
+        *written = self.generation;
+
+        Ok(())
     }
 }
 
-#[derive(Clone, Hash, PartialEq, Eq)]
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Recordkey {
     pub fqdn: String,
     pub record_type: RecordType,
@@ -79,7 +417,7 @@ impl TryFrom<Endpoint> for Recordkey {
     }
 }
 
-#[derive(Clone, Hash, PartialEq, Eq)]
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
 #[allow(clippy::upper_case_acronyms)]
 pub enum RecordType {
     A,
@@ -104,7 +442,7 @@ impl TryFrom<String> for RecordType {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct RecordEntry {
     pub uuid: String,
     pub server: String,
@@ -148,3 +486,121 @@ impl ZoneCache for DefaultZoneCache {
         self.0.clone().into_iter().collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // scratch returns a fresh directory for a test.
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("record-cache-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn record(hostname: &str, server: &str) -> HostOverrideRecord {
+        HostOverrideRecord {
+            uuid: format!("uuid-{hostname}"),
+            enabled: "1".to_owned(),
+            domain: "home.arpa".to_owned(),
+            rr: "A".to_owned(),
+            server: server.to_owned(),
+            hostname: hostname.to_owned(),
+            mx: String::new(),
+            mxprio: String::new(),
+            description: String::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn file_cache_round_trip() {
+        let dir = scratch("round-trip");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("cache.json");
+        let mut cache = FileRecordCache::try_open(&path).unwrap();
+        assert!(cache.take_snapshot().unwrap().is_none());
+
+        cache
+            .try_replace_all(&[record("a", "10.0.0.1"), record("b", "10.0.0.2")])
+            .unwrap();
+        cache.try_insert_record(&record("c", "10.0.0.3")).unwrap();
+        cache.try_remove_record(&record("b", "10.0.0.2")).unwrap();
+        cache
+            .take_snapshot()
+            .unwrap()
+            .unwrap()
+            .write()
+            .await
+            .unwrap();
+        assert!(cache.take_snapshot().unwrap().is_none());
+
+        let reopened = FileRecordCache::try_open(&path).unwrap();
+        let entry = reopened
+            .try_get_record(&record("a", "10.0.0.1"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            (entry.uuid.as_str(), entry.server.as_str()),
+            ("uuid-a", "10.0.0.1")
+        );
+        assert!(entry.enabled);
+        assert!(reopened
+            .try_get_record(&record("b", "10.0.0.2"))
+            .unwrap()
+            .is_none());
+        assert!(reopened
+            .try_get_record(&record("c", "10.0.0.3"))
+            .unwrap()
+            .is_some());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn late_snapshot_does_not_overwrite_newer_one() {
+        let dir = scratch("late");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("cache.json");
+        let mut cache = FileRecordCache::try_open(&path).unwrap();
+
+        cache.try_insert_record(&record("a", "10.0.0.1")).unwrap();
+        let older = cache.take_snapshot().unwrap().unwrap();
+        cache.try_insert_record(&record("b", "10.0.0.2")).unwrap();
+        let newer = cache.take_snapshot().unwrap().unwrap();
+
+        newer.write().await.unwrap();
+        older.write().await.unwrap();
+
+        let reopened = FileRecordCache::try_open(&path).unwrap();
+        assert!(reopened
+            .try_get_record(&record("b", "10.0.0.2"))
+            .unwrap()
+            .is_some());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn snapshot_retried_after_failed_write() {
+        let dir = scratch("retry");
+        let path = dir.join("cache.json");
+        let mut cache = FileRecordCache::try_open(&path).unwrap();
+
+        cache.try_insert_record(&record("a", "10.0.0.1")).unwrap();
+        let snapshot = cache.take_snapshot().unwrap().unwrap();
+        assert!(snapshot.write().await.is_err());
+
+        std::fs::create_dir_all(&dir).unwrap();
+        let snapshot = cache.take_snapshot().unwrap().unwrap();
+        snapshot.write().await.unwrap();
+        assert!(cache.take_snapshot().unwrap().is_none());
+
+        let reopened = FileRecordCache::try_open(&path).unwrap();
+        assert!(reopened
+            .try_get_record(&record("a", "10.0.0.1"))
+            .unwrap()
+            .is_some());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,118 @@
+use crate::config::TargetsConfig;
+use crate::external_dns::{Endpoint, Targets};
+use std::net::IpAddr;
+
+// Targets of endpoints must lie within the allowed networks and
+// outside the denied ones, so addresses such as pod or public IPs
+// are not published by accident. Without any network configured
+// every target is accepted.
+
+// is_allowed tells whether the target may be published.
+fn is_allowed(target: &str, config: &TargetsConfig) -> bool {
+    let Ok(ip) = target.parse::<IpAddr>() else {
+        return false;
+    };
+
+    (config.allow.is_empty() || config.allow.iter().any(|n| n.contains(&ip)))
+        && !config.deny.iter().any(|n| n.contains(&ip))
+}
+
+// filter removes the targets of the endpoint which may not be
+// published, and drops the endpoint when none is left.
+pub fn filter(endpoint: Endpoint, config: &TargetsConfig) -> Option<Endpoint> {
+    if config.allow.is_empty() && config.deny.is_empty() {
+        return Some(endpoint);
+    }
+
+    let (allowed, denied): (Vec<_>, Vec<_>) = endpoint
+        .targets
+        .clone()
+        .into_iter()
+        .partition(|t| is_allowed(t, config));
+
+    for target in &denied {
+        tracing::warn!(
+            dns_name = endpoint.dns_name,
+            target,
+            "dropping target outside the allowed networks"
+        );
+    }
+
+    if allowed.is_empty() {
+        tracing::warn!(
+            dns_name = endpoint.dns_name,
+            "dropping endpoint without any allowed target"
+        );
+        return None;
+    }
+
+    Some(Endpoint {
+        targets: Targets(allowed),
+        ..endpoint
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(allow: &[&str], deny: &[&str]) -> TargetsConfig {
+        TargetsConfig {
+            allow: allow.iter().map(|n| n.parse().unwrap()).collect(),
+            deny: deny.iter().map(|n| n.parse().unwrap()).collect(),
+        }
+    }
+
+    fn endpoint(targets: &[&str]) -> Endpoint {
+        Endpoint {
+            dns_name: "www.home.arpa".to_owned(),
+            targets: Targets(targets.iter().map(|t| t.to_string()).collect()),
+            record_type: "A".to_owned(),
+            ..Default::default()
+        }
+    }
+
+    fn targets(endpoint: Option<Endpoint>) -> Option<Vec<String>> {
+        endpoint.map(|ep| ep.targets.0)
+    }
+
+    #[test]
+    fn everything_allowed_without_networks() {
+        let ep = filter(endpoint(&["203.0.113.1", "not an ip"]), &config(&[], &[]));
+
+        assert_eq!(targets(ep).unwrap(), ["203.0.113.1", "not an ip"]);
+    }
+
+    #[test]
+    fn deny_takes_precedence_over_allow() {
+        let config = config(&["10.0.0.0/8"], &["10.42.0.0/16"]);
+
+        let ep = filter(endpoint(&["10.0.0.1", "10.42.0.7"]), &config);
+        assert_eq!(targets(ep).unwrap(), ["10.0.0.1"]);
+
+        assert!(filter(endpoint(&["10.42.0.7"]), &config).is_none());
+    }
+
+    #[test]
+    fn only_allowed_networks() {
+        let config = config(&["10.0.0.0/8", "fd00::/8"], &[]);
+
+        let ep = filter(endpoint(&["10.0.0.1", "fd00::1", "203.0.113.1"]), &config);
+        assert_eq!(targets(ep).unwrap(), ["10.0.0.1", "fd00::1"]);
+    }
+
+    #[test]
+    fn only_denied_networks() {
+        let config = config(&[], &["10.42.0.0/16"]);
+
+        let ep = filter(endpoint(&["203.0.113.1", "10.42.0.7"]), &config);
+        assert_eq!(targets(ep).unwrap(), ["203.0.113.1"]);
+    }
+
+    #[test]
+    fn invalid_targets_dropped_once_filtering() {
+        let config = config(&[], &["10.42.0.0/16"]);
+
+        assert!(filter(endpoint(&["not an ip"]), &config).is_none());
+    }
+}
@@ -0,0 +1,327 @@
+use crate::cli::{managed_records, rr_type, CliState};
+use crate::config::{BackendKind, Config};
+use crate::external_dns::{Endpoint, Targets};
+use crate::opnsense::unbound::HostOverrideRecord;
+use crate::opnsense::Backend;
+use crate::{replicate, zones};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+// The transfer module exports the managed host overrides to a
+// BIND style zone file fragment or JSON, and imports such files
+// back, converging OPNsense towards their content.
+
+#[derive(clap::ValueEnum, Clone, Copy)]
+pub enum TransferFormat {
+    Zone,
+    Json,
+}
+
+impl TransferFormat {
+    // from_path guesses the format from the file extension,
+    // defaulting to zone files.
+    fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => Self::Json,
+            _ => Self::Zone,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TransferRecord {
+    pub name: String,
+    pub r#type: String,
+    pub target: String,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub description: String,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl From<&HostOverrideRecord> for TransferRecord {
+    fn from(r: &HostOverrideRecord) -> Self {
+        Self {
+            name: format!("{}.{}", r.hostname, r.domain),
+            r#type: rr_type(r).to_uppercase(),
+            target: r.server.clone(),
+            enabled: r.enabled.trim() == "1",
+            description: r.description.clone(),
+        }
+    }
+}
+
+pub async fn export(
+    config: &Config,
+    zone: Option<String>,
+    format: TransferFormat,
+    output: Option<&Path>,
+) -> anyhow::Result<()> {
+    let state = CliState::try_from(config)?;
+    let zone = zone.map(|z| z.trim_end_matches('.').to_owned());
+
+    let mut records = managed_records(&state)
+        .await?
+        .iter()
+        .filter(|r| zone.is_none() || zone.as_ref() == Some(&r.domain))
+        .filter(|r| ["A", "AAAA"].contains(&rr_type(r).to_uppercase().as_str()))
+        .map(TransferRecord::from)
+        .collect::<Vec<_>>();
+    records.sort_by(|a, b| (&a.name, &a.r#type).cmp(&(&b.name, &b.r#type)));
+
+    let buf = match format {
+        TransferFormat::Json => serde_json::to_string_pretty(&records)? + "\n",
+        TransferFormat::Zone => to_zone_file(&records),
+    };
+
+    match output {
+        Some(path) => std::fs::write(path, buf)?,
+        None => print!("{buf}"),
+    }
+
+    Ok(())
+}
+
+// import creates the host overrides found in the file that
+// do not exist yet and updates those that differ. Host overrides
+// missing from the file are left alone.
+pub async fn import(
+    config: &Config,
+    input: &Path,
+    format: Option<TransferFormat>,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let state = CliState::try_from(config)?;
+    let zones = zones(&state).await?;
+
+    let buf = std::fs::read_to_string(input)?;
+    let records = match format.unwrap_or_else(|| TransferFormat::from_path(input)) {
+        TransferFormat::Json => serde_json::from_str::<Vec<TransferRecord>>(&buf)?
+            .into_iter()
+            .map(|r| TransferRecord {
+                r#type: r.r#type.to_uppercase(),
+                ..r
+            })
+            .collect(),
+        TransferFormat::Zone => parse_zone_file(&buf)?,
+    };
+
+    let disabled = records.iter().filter(|r| !r.enabled).count();
+    if disabled > 0 && config.backend == BackendKind::Dnsmasq {
+        anyhow::bail!(
+            "{disabled} disabled record(s) in {}, dnsmasq host entries cannot be disabled",
+            input.display()
+        );
+    }
+
+    let existing = managed_records(&state).await?;
+    let backend = state.opnsense.backend();
+    let mut changed = 0;
+    let (mut updates, mut creates) = (vec![], vec![]);
+    let mut result = Ok(());
+
+    for r in records {
+        let endpoint = Endpoint {
+            dns_name: r.name.clone(),
+            targets: Targets(vec![r.target.clone()]),
+            record_type: r.r#type.clone(),
+            ..Default::default()
+        };
+        let Some(mut record) = endpoint.get_record_for_zones(&zones) else {
+            println!("skipped {} {}: not in a managed zone", r.name, r.r#type);
+            continue;
+        };
+        record.enabled = if r.enabled { "1" } else { "0" }.to_owned();
+        record.description = r.description.clone();
+
+        let current = existing.iter().find(|e| {
+            e.hostname == record.hostname
+                && e.domain == record.domain
+                && rr_type(e).eq_ignore_ascii_case(&r.r#type)
+        });
+
+        match current {
+            Some(e) if TransferRecord::from(e) == r => continue,
+            Some(e) => {
+                println!("update {} {} {}", r.name, r.r#type, r.target);
+                if !dry_run {
+                    if let Err(e) = backend.set_record(&e.uuid, &record).await {
+                        result = Err(e);
+                        break;
+                    }
+                    updates.push(record);
+                }
+            }
+            None => {
+                println!("create {} {} {}", r.name, r.r#type, r.target);
+                if !dry_run {
+                    if let Err(e) = backend.add_record(&record).await {
+                        result = Err(e);
+                        break;
+                    }
+                    creates.push(record);
+                }
+            }
+        }
+
+        changed += 1;
+    }
+
+    replicate(&state, &[], &updates, &creates).await;
+    result?;
+
+    println!("{changed} host override(s) to change");
+
+    if changed > 0 && !dry_run {
+        backend.apply().await?;
+    }
+
+    Ok(())
+}
+
+// to_zone_file renders records as zone file lines. Disabled
+// records are commented out behind a marker parse_zone_file
+// recognizes, so they survive a round trip.
+fn to_zone_file(records: &[TransferRecord]) -> String {
+    records
+        .iter()
+        .map(|r| {
+            let comment = match r.description.as_str() {
+                "" => String::new(),
+                d => format!(" ; {d}"),
+            };
+            let line = format!("{}. IN {} {}{comment}\n", r.name, r.r#type, r.target);
+
+            match r.enabled {
+                true => line,
+                false => format!("{DISABLED_MARKER}{line}"),
+            }
+        })
+        .collect()
+}
+
+const DISABLED_MARKER: &str = "; disabled: ";
+
+// parse_zone_file reads A and AAAA records from a BIND style
+// zone file fragment. Relative names are resolved against
+// $ORIGIN; TTLs and classes are accepted and ignored, other
+// record types are skipped. Records written out disabled by
+// to_zone_file are read back disabled.
+fn parse_zone_file(buf: &str) -> anyhow::Result<Vec<TransferRecord>> {
+    let mut origin: Option<String> = None;
+    let mut records = vec![];
+
+    for (n, line) in buf.lines().enumerate() {
+        let (line, enabled) = match line.trim_start().strip_prefix(DISABLED_MARKER.trim_end()) {
+            Some(line) => (line, false),
+            None => (line, true),
+        };
+        let (content, comment) = line.split_once(';').unwrap_or((line, ""));
+        let mut fields = content.split_whitespace().peekable();
+
+        let Some(first) = fields.next() else {
+            continue;
+        };
+
+        if first.eq_ignore_ascii_case("$ORIGIN") {
+            origin = fields.next().map(|o| o.trim_end_matches('.').to_owned());
+            continue;
+        }
+        if first.starts_with('$') {
+            continue;
+        }
+
+        let name = match (first.strip_suffix('.'), &origin) {
+            (Some(name), _) => name.to_owned(),
+            (None, _) if first == "@" => origin
+                .clone()
+                .ok_or(anyhow::anyhow!("line {}: @ without $ORIGIN", n + 1))?,
+            (None, Some(origin)) => format!("{first}.{origin}"),
+            (None, None) => anyhow::bail!("line {}: relative name without $ORIGIN", n + 1),
+        };
+
+        while fields
+            .next_if(|f| f.parse::<u64>().is_ok() || ["IN", "in"].contains(f))
+            .is_some()
+        {}
+
+        let (Some(r#type), Some(target)) = (fields.next(), fields.next()) else {
+            anyhow::bail!("line {}: missing record type or data", n + 1);
+        };
+
+        let r#type = r#type.to_uppercase();
+        if !["A", "AAAA"].contains(&r#type.as_str()) {
+            tracing::warn!(name, r#type, "skipping unsupported record type");
+            continue;
+        }
+
+        records.push(TransferRecord {
+            name,
+            r#type,
+            target: target.to_owned(),
+            enabled,
+            description: comment.trim().to_owned(),
+        });
+    }
+
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(
+        name: &str,
+        r#type: &str,
+        target: &str,
+        enabled: bool,
+        description: &str,
+    ) -> TransferRecord {
+        TransferRecord {
+            name: name.to_owned(),
+            r#type: r#type.to_owned(),
+            target: target.to_owned(),
+            enabled,
+            description: description.to_owned(),
+        }
+    }
+
+    #[test]
+    fn zone_file_round_trip() {
+        let records = vec![
+            record("a.home.arpa", "A", "10.0.0.1", true, ""),
+            record("b.home.arpa", "AAAA", "fd00::1", true, "printer"),
+            record("c.home.arpa", "A", "10.0.0.3", false, ""),
+            record("d.home.arpa", "A", "10.0.0.4", false, "spare; unused"),
+        ];
+
+        assert_eq!(parse_zone_file(&to_zone_file(&records)).unwrap(), records);
+    }
+
+    #[test]
+    fn zone_file_relative_names() {
+        let buf = "$ORIGIN home.arpa.\n\
+                   @ 300 IN A 10.0.0.1\n\
+                   www IN a 10.0.0.2 ; web\n\
+                   mail MX 10 mx.home.arpa.\n\
+                   ; a comment\n";
+
+        assert_eq!(
+            parse_zone_file(buf).unwrap(),
+            vec![
+                record("home.arpa", "A", "10.0.0.1", true, ""),
+                record("www.home.arpa", "A", "10.0.0.2", true, "web"),
+            ]
+        );
+    }
+
+    #[test]
+    fn zone_file_relative_name_without_origin() {
+        assert!(parse_zone_file("www IN A 10.0.0.1\n").is_err());
+    }
+}
@@ -0,0 +1,36 @@
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+// spawn polls the modification time of the given files and
+// calls on_change whenever any of them differs from the last
+// poll. Polling (rather than inotify) keeps working with the
+// symlink swaps Kubernetes does for mounted secrets.
+pub fn spawn<F>(paths: Vec<PathBuf>, interval: Duration, mut on_change: F)
+where
+    F: FnMut() + Send + 'static,
+{
+    tokio::spawn(async move {
+        let mut last = modified(&paths);
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        ticker.tick().await;
+
+        loop {
+            ticker.tick().await;
+
+            let current = modified(&paths);
+            if current != last {
+                tracing::info!(?paths, "watched files changed");
+                last = current;
+                on_change();
+            }
+        }
+    });
+}
+
+fn modified(paths: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    paths
+        .iter()
+        .map(|p| std::fs::metadata(p).and_then(|m| m.modified()).ok())
+        .collect()
+}